@@ -0,0 +1,535 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+
+const START: usize = 0x200;
+// Call depths past this are taken as unbounded recursion
+const STACK_LIMIT: usize = 16;
+// Stops the walk on programs whose I register takes too many values
+const MAX_STATES: usize = 500_000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Chip8,
+    Schip,
+    XoChip
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "CHIP-8"),
+            Platform::Schip => write!(f, "SCHIP"),
+            Platform::XoChip => write!(f, "XO-CHIP")
+        }
+    }
+}
+
+pub struct Report {
+    pub size: usize,
+    pub overflow: usize,
+    pub platform: Platform,
+    pub reached: usize,
+    pub truncated: bool,
+    pub invalid: Vec<(u16, u16)>,
+    pub unsupported: Vec<(u16, u16)>,
+    pub outside: Vec<(u16, u16)>,
+    pub unreachable: Vec<(u16, u16)>,
+    pub max_depth: Option<usize>,
+    pub underflows: Vec<u16>,
+    pub low_writes: Vec<u16>,
+    pub self_modifying: Vec<u16>,
+    pub shifts: Vec<(u16, bool)>,
+    pub load_stores: Vec<u16>,
+    pub moved_i_uses: Vec<u16>,
+    pub jump_plus: Vec<u16>,
+    pub add_i: Vec<u16>
+}
+
+// A point of the walk, I is tracked while it is a constant
+// and `moved` remembers an FX55/FX65 that left I somewhere quirk dependent
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    pc: u16,
+    depth: usize,
+    i: Option<u16>,
+    moved: bool
+}
+
+pub fn analyze(rom: &[u8]) -> Report {
+    // only what fits in memory is loaded, the rest is reported as overflow
+    let mut memory = [0_u8; 4096];
+    let size = rom.len().min(4096 - START);
+    let end = START + size;
+    memory[START..end].copy_from_slice(&rom[..size]);
+
+    let mut platform = Platform::Chip8;
+    let mut max_depth = 0;
+    let mut recursive = false;
+    let mut executed = vec![false; size];
+    let mut writes = vec![];
+    let mut invalid = BTreeSet::new();
+    let mut unsupported = BTreeSet::new();
+    let mut outside = BTreeSet::new();
+    let mut underflows = BTreeSet::new();
+    let mut low_writes = BTreeSet::new();
+    let mut shifts = BTreeSet::new();
+    let mut load_stores = BTreeSet::new();
+    let mut moved_i_uses = BTreeSet::new();
+    let mut jump_plus = BTreeSet::new();
+    let mut add_i = BTreeSet::new();
+
+    let mut seen = HashSet::new();
+    let mut pending = vec![State { pc: START as u16, depth: 0, i: None, moved: false }];
+
+    while let Some(state) = pending.pop() {
+        if seen.len() >= MAX_STATES {
+            break;
+        }
+        if !seen.insert(state) {
+            continue;
+        }
+
+        let pc = state.pc as usize;
+        if pc < START || pc + 1 >= end {
+            outside.insert((state.pc, state.pc));
+            continue;
+        }
+
+        let opcode = (memory[pc] as u16) << 8 | memory[pc + 1] as u16;
+        let kind = classify(opcode);
+        let length = if opcode == 0xF000 { 4 } else { 2 };
+        for byte in pc..(pc + length).min(end) {
+            executed[byte - START] = true;
+        }
+        max_depth = max_depth.max(state.depth);
+
+        match kind {
+            Kind::Invalid => {
+                invalid.insert((state.pc, opcode));
+                continue;
+            },
+            Kind::Schip | Kind::XoChip => {
+                unsupported.insert((state.pc, opcode));
+                if kind == Kind::XoChip {
+                    platform = Platform::XoChip;
+                } else if platform == Platform::Chip8 {
+                    platform = Platform::Schip;
+                }
+            },
+            Kind::Chip8 => ()
+        }
+
+        let x = (opcode >> 8 & 15) as u8;
+        let y = (opcode >> 4 & 15) as u8;
+        let nnn = opcode & 0xFFF;
+        let next = state.pc + length as u16;
+        let uses_i = match opcode >> 12 {
+            0xD => true,
+            0x5 => opcode & 15 == 2 || opcode & 15 == 3,
+            0xF => matches!(opcode & 0xFF, 0x1E | 0x33 | 0x55 | 0x65),
+            _ => false
+        };
+
+        if uses_i && state.moved {
+            moved_i_uses.insert(state.pc);
+        }
+
+        // memory written through I: FX33, FX55 and the XO-CHIP 5XY2
+        let written = match (opcode >> 12, opcode & 0xFF, opcode & 15) {
+            (0xF, 0x33, _) => 3,
+            (0xF, 0x55, _) => x as u16 + 1,
+            (0x5, _, 2) => (x as i16 - y as i16).unsigned_abs() + 1,
+            _ => 0
+        };
+        if let (true, Some(i)) = (written > 0, state.i) {
+            if (i as usize) < START {
+                low_writes.insert(state.pc);
+            }
+            writes.push((state.pc, i, i.saturating_add(written)));
+        }
+
+        let mut follow = |pc: u16, depth: usize, i: Option<u16>, moved: bool| {
+            if depth > STACK_LIMIT {
+                recursive = true;
+            } else {
+                pending.push(State { pc, depth, i, moved });
+            }
+        };
+
+        let (i, moved) = (state.i, state.moved);
+        let skip = next + if long_at(&memory, next) { 4 } else { 2 };
+        match opcode >> 12 {
+            0x0 => match opcode {
+                0x00EE => {
+                    if state.depth == 0 {
+                        underflows.insert(state.pc);
+                    }
+                },
+                0x00FD => (),
+                _ => follow(next, state.depth, i, moved)
+            },
+            0x1 => {
+                if !in_program(nnn, end) {
+                    outside.insert((state.pc, nnn));
+                } else if nnn != state.pc {
+                    follow(nnn, state.depth, i, moved);
+                }
+            },
+            0x2 => {
+                if in_program(nnn, end) {
+                    follow(nnn, state.depth + 1, i, moved);
+                } else {
+                    outside.insert((state.pc, nnn));
+                }
+                follow(next, state.depth, None, false);
+            },
+            0x3 | 0x4 | 0x9 => {
+                follow(next, state.depth, i, moved);
+                follow(skip, state.depth, i, moved);
+            },
+            0x5 if opcode & 15 == 0 => {
+                follow(next, state.depth, i, moved);
+                follow(skip, state.depth, i, moved);
+            },
+            0x8 => {
+                if opcode & 15 == 0x6 || opcode & 15 == 0xE {
+                    shifts.insert((state.pc, x != y));
+                }
+                follow(next, state.depth, i, moved);
+            },
+            0xA => follow(next, state.depth, Some(nnn), false),
+            0xB => {
+                // the targets depend on V0, only the V0 = 0 one is followed
+                jump_plus.insert(state.pc);
+                if in_program(nnn, end) {
+                    follow(nnn, state.depth, i, moved);
+                }
+            },
+            0xE => {
+                follow(next, state.depth, i, moved);
+                follow(skip, state.depth, i, moved);
+            },
+            0xF => match opcode & 0xFF {
+                0x00 if opcode == 0xF000 => {
+                    let long = (memory[(pc + 2) % 4096] as u16) << 8 | memory[(pc + 3) % 4096] as u16;
+                    follow(next, state.depth, Some(long), false);
+                },
+                0x1E => {
+                    add_i.insert(state.pc);
+                    follow(next, state.depth, None, false);
+                },
+                0x29 | 0x30 => follow(next, state.depth, None, false),
+                0x55 | 0x65 => {
+                    load_stores.insert(state.pc);
+                    follow(next, state.depth, None, true);
+                },
+                _ => follow(next, state.depth, i, moved)
+            },
+            _ => follow(next, state.depth, i, moved)
+        }
+    }
+
+    let mut self_modifying = BTreeSet::new();
+    for (pc, from, to) in writes {
+        if (from as usize..to as usize).any(|byte| byte >= START && byte < end && executed[byte - START]) {
+            self_modifying.insert(pc);
+        }
+    }
+
+    let mut unreachable = vec![];
+    let mut byte = 0;
+    while byte < executed.len() {
+        let from = byte;
+        while byte < executed.len() && !executed[byte] {
+            byte += 1;
+        }
+        if byte > from {
+            unreachable.push(((START + from) as u16, (START + byte - 1) as u16));
+        }
+        byte += 1;
+    }
+
+    Report {
+        size,
+        overflow: rom.len() - size,
+        platform,
+        reached: executed.iter().filter(|&&byte| byte).count(),
+        truncated: seen.len() >= MAX_STATES,
+        invalid: invalid.into_iter().collect(),
+        unsupported: unsupported.into_iter().collect(),
+        outside: outside.into_iter().collect(),
+        unreachable,
+        max_depth: if recursive { None } else { Some(max_depth) },
+        underflows: underflows.into_iter().collect(),
+        low_writes: low_writes.into_iter().collect(),
+        self_modifying: self_modifying.into_iter().collect(),
+        shifts: shifts.into_iter().collect(),
+        load_stores: load_stores.into_iter().collect(),
+        moved_i_uses: moved_i_uses.into_iter().collect(),
+        jump_plus: jump_plus.into_iter().collect(),
+        add_i: add_i.into_iter().collect()
+    }
+}
+
+// XO-CHIP's F000 NNNN is the only four byte instruction, skips jump over all of it
+fn long_at(memory: &[u8; 4096], address: u16) -> bool {
+    let address = address as usize;
+    address + 1 < 4096 && memory[address] == 0xF0 && memory[address + 1] == 0x00
+}
+
+fn in_program(address: u16, end: usize) -> bool {
+    address as usize >= START && (address as usize) < end
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Chip8,
+    Schip,
+    XoChip,
+    Invalid
+}
+
+fn classify(opcode: u16) -> Kind {
+    let n = opcode & 15;
+    let nn = opcode & 0xFF;
+    match opcode >> 12 {
+        0x0 => match opcode {
+            0x00E0 | 0x00EE => Kind::Chip8,
+            0x00FB..=0x00FF => Kind::Schip,
+            0x00C1..=0x00CF => Kind::Schip,
+            0x00D1..=0x00DF => Kind::XoChip,
+            _ => Kind::Invalid
+        },
+        0x5 => match n {
+            0 => Kind::Chip8,
+            2 | 3 => Kind::XoChip,
+            _ => Kind::Invalid
+        },
+        0x8 => match n {
+            0x0..=0x7 | 0xE => Kind::Chip8,
+            _ => Kind::Invalid
+        },
+        0x9 if n != 0 => Kind::Invalid,
+        0xD if n == 0 => Kind::Schip,
+        0xE => match nn {
+            0x9E | 0xA1 => Kind::Chip8,
+            _ => Kind::Invalid
+        },
+        0xF => match nn {
+            0x07 | 0x0A | 0x15 | 0x18 | 0x1E | 0x29 | 0x33 | 0x55 | 0x65 => Kind::Chip8,
+            0x30 | 0x75 | 0x85 => Kind::Schip,
+            0x00 if opcode == 0xF000 => Kind::XoChip,
+            0x01 | 0x3A => Kind::XoChip,
+            0x02 if opcode == 0xF002 => Kind::XoChip,
+            _ => Kind::Invalid
+        },
+        _ => Kind::Chip8
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "platform: {}", self.platform)?;
+        writeln!(f, "size: {} bytes (0x200-0x{:X})", self.size, START + self.size.max(1) - 1)?;
+        if self.overflow > 0 {
+            writeln!(f, "{} bytes past 0xFFF do not fit in memory and are left out", self.overflow)?;
+        }
+        writeln!(f, "reached: {} bytes of code", self.reached)?;
+        match self.max_depth {
+            Some(depth) => writeln!(f, "call depth: {}", depth)?,
+            None => writeln!(f, "call depth: unbounded, recursion or jumps out of subroutines grow the stack past {}", STACK_LIMIT)?
+        }
+        if self.truncated {
+            writeln!(f, "the analysis stopped after {} states, results are partial", MAX_STATES)?;
+        }
+
+        section(f, "invalid opcodes", self.invalid.iter().map(|&(pc, opcode)| {
+            format!("{:03X}: {:04X}", pc, opcode)
+        }))?;
+        section(f, "opcodes this emulator does not run", self.unsupported.iter().map(|&(pc, opcode)| {
+            format!("{:03X}: {:04X}", pc, opcode)
+        }))?;
+        section(f, "jumps and calls outside the program", self.outside.iter().map(|&(pc, target)| {
+            if pc == target {
+                format!("{:03X}: runs past the end of the program", pc)
+            } else {
+                format!("{:03X}: to {:03X}", pc, target)
+            }
+        }))?;
+        section(f, "returns with an empty stack", self.underflows.iter().map(|pc| format!("{:03X}", pc)))?;
+        section(f, "not reached, data or dead code", self.unreachable.iter().map(|&(from, to)| {
+            format!("{:03X}-{:03X} ({} bytes)", from, to, to - from + 1)
+        }))?;
+        section(f, "writes below 0x200", self.low_writes.iter().map(|pc| format!("{:03X}", pc)))?;
+        section(f, "self-modifying code", self.self_modifying.iter().map(|pc| format!("{:03X}", pc)))?;
+
+        section(f, "shift quirk (8XY6/8XYE)", self.shifts.iter().map(|&(pc, different)| {
+            if different {
+                format!("{:03X}: VX and VY differ, the result depends on the quirk", pc)
+            } else {
+                format!("{:03X}", pc)
+            }
+        }))?;
+        section(f, "load/store quirk (FX55/FX65)", self.load_stores.iter().map(|pc| format!("{:03X}", pc)))?;
+        section(f, "I used after FX55/FX65 without reloading it, depends on the load/store quirk", self.moved_i_uses.iter().map(|pc| {
+            format!("{:03X}", pc)
+        }))?;
+        section(f, "jump quirk (BNNN), only the V0 = 0 target is followed", self.jump_plus.iter().map(|pc| format!("{:03X}", pc)))?;
+        section(f, "I overflow quirk (FX1E)", self.add_i.iter().map(|pc| format!("{:03X}", pc)))
+    }
+}
+
+fn section<I: Iterator<Item = String>>(f: &mut fmt::Formatter, title: &str, lines: I) -> fmt::Result {
+    let lines: Vec<String> = lines.collect();
+    if !lines.is_empty() {
+        writeln!(f)?;
+        writeln!(f, "{}:", title)?;
+        for line in lines {
+            writeln!(f, "  {}", line)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze, Platform, MAX_STATES};
+
+    #[test]
+    fn a_jump_loop_is_all_reached() {
+        // LDB 0, 1; JP 200
+        let report = analyze(&[0x60, 0x01, 0x12, 0x00]);
+        assert_eq!(report.reached, 4);
+        assert!(report.unreachable.is_empty());
+        assert!(report.outside.is_empty());
+        assert_eq!(report.max_depth, Some(0));
+        assert!(!report.truncated);
+    }
+
+    #[test]
+    fn branches_that_meet_again_are_walked_once() {
+        // SEB 0, 0; ADDB 0, 1; LDI 300; DRW 0, 0, 1; JP 200
+        let report = analyze(&[0x30, 0x00, 0x70, 0x01, 0xA3, 0x00, 0xD0, 0x01, 0x12, 0x00]);
+        assert_eq!(report.reached, 10);
+        assert!(!report.truncated);
+    }
+
+    #[test]
+    fn a_call_returns_to_the_caller() {
+        // CALL 206; JP 202; 2 bytes of data; RET
+        let report = analyze(&[0x22, 0x06, 0x12, 0x02, 0xAB, 0xCD, 0x00, 0xEE]);
+        assert_eq!(report.max_depth, Some(1));
+        assert!(report.underflows.is_empty());
+        assert_eq!(report.unreachable, vec![(0x204, 0x205)]);
+    }
+
+    #[test]
+    fn returning_with_an_empty_stack_is_reported() {
+        // RET
+        assert_eq!(analyze(&[0x00, 0xEE]).underflows, vec![0x200]);
+    }
+
+    #[test]
+    fn recursion_is_unbounded_depth() {
+        // CALL 200
+        assert_eq!(analyze(&[0x22, 0x00]).max_depth, None);
+    }
+
+    #[test]
+    fn a_data_tail_is_unreachable() {
+        // JP 200 and three bytes of data
+        let report = analyze(&[0x12, 0x00, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(report.reached, 2);
+        assert_eq!(report.unreachable, vec![(0x202, 0x204)]);
+        assert!(report.invalid.is_empty());
+    }
+
+    #[test]
+    fn only_what_fits_in_memory_is_analyzed() {
+        // JP 200 and data up to the end of memory and 16 bytes past it
+        let mut rom = vec![0x12, 0x00];
+        rom.resize(4096 - 0x200 + 16, 0xFF);
+        let report = analyze(&rom);
+        assert_eq!(report.size, 4096 - 0x200);
+        assert_eq!(report.overflow, 16);
+        assert_eq!(report.unreachable, vec![(0x202, 0xFFF)]);
+    }
+
+    #[test]
+    fn an_schip_opcode_makes_it_an_schip_game() {
+        // JP 200
+        assert!(analyze(&[0x12, 0x00]).platform == Platform::Chip8);
+
+        // HIGH; JP 202
+        let report = analyze(&[0x00, 0xFF, 0x12, 0x02]);
+        assert!(report.platform == Platform::Schip);
+        assert_eq!(report.unsupported, vec![(0x200, 0x00FF)]);
+    }
+
+    #[test]
+    fn too_many_states_stop_the_walk() {
+        // SEB 0, 0; LDI n over and over, every skip carries each I set before it along
+        let mut rom = vec![];
+        for n in 0..(4096 - 0x200) / 4 - 1 {
+            rom.extend(&[0x30, 0x00, 0xA0 | (n >> 8) as u8, n as u8]);
+        }
+        let end = 0x200 + rom.len() as u16;
+        rom.extend(&[0x10 | (end >> 8) as u8, end as u8]);
+
+        let report = analyze(&rom);
+        assert!(report.truncated);
+        assert!(report.to_string().contains(&format!("stopped after {} states", MAX_STATES)));
+    }
+
+    #[test]
+    fn writes_below_0x200_are_reported() {
+        // LDI 100; DUMP 2; JP 204
+        let report = analyze(&[0xA1, 0x00, 0xF2, 0x55, 0x12, 0x04]);
+        assert_eq!(report.low_writes, vec![0x202]);
+        assert!(report.self_modifying.is_empty());
+    }
+
+    #[test]
+    fn writes_into_reached_code_are_self_modifying() {
+        // LDI 200; DUMP 0; JP 204
+        let report = analyze(&[0xA2, 0x00, 0xF0, 0x55, 0x12, 0x04]);
+        assert_eq!(report.self_modifying, vec![0x202]);
+        assert!(report.low_writes.is_empty());
+
+        // LDI 206; BCD 0; JP 204 and three bytes of data
+        let report = analyze(&[0xA2, 0x06, 0xF0, 0x33, 0x12, 0x04, 0x00, 0x00, 0x00]);
+        assert!(report.self_modifying.is_empty());
+    }
+
+    #[test]
+    fn shifts_tell_whether_the_quirk_changes_their_result() {
+        // SHR 0, 1; SHL 0, 0; JP 204
+        let report = analyze(&[0x80, 0x16, 0x80, 0x0E, 0x12, 0x04]);
+        assert_eq!(report.shifts, vec![(0x200, true), (0x202, false)]);
+        assert!(report.to_string().contains("shift quirk (8XY6/8XYE)"));
+    }
+
+    #[test]
+    fn i_used_after_a_load_without_reloading_it_is_reported() {
+        // LDI 300; LOAD 1; DRW 0, 0, 1; LDI 300; DRW 0, 0, 1; JP 20A
+        let report = analyze(&[0xA3, 0x00, 0xF1, 0x65, 0xD0, 0x01, 0xA3, 0x00, 0xD0, 0x01, 0x12, 0x0A]);
+        assert_eq!(report.load_stores, vec![0x202]);
+        assert_eq!(report.moved_i_uses, vec![0x204]);
+    }
+
+    #[test]
+    fn an_xo_chip_opcode_makes_it_an_xo_chip_game() {
+        // LDI long 300; SAVE 0, 1; LOAD 0, 1; JP 208
+        let report = analyze(&[0xF0, 0x00, 0x03, 0x00, 0x50, 0x12, 0x50, 0x13, 0x12, 0x08]);
+        assert!(report.platform == Platform::XoChip);
+        assert_eq!(report.unsupported, vec![(0x200, 0xF000), (0x204, 0x5012), (0x206, 0x5013)]);
+        assert!(report.low_writes.is_empty());
+
+        // SEB 0, 0; LDI long 300; JP 200, the skip jumps over all four bytes
+        let report = analyze(&[0x30, 0x00, 0xF0, 0x00, 0x03, 0x00, 0x12, 0x00]);
+        assert!(report.invalid.is_empty());
+        assert_eq!(report.reached, 8);
+    }
+}
@@ -1,81 +1,315 @@
-pub fn assemble(text: String) -> Vec<u8> {
+use image;
+use mnemonics::{self, Field};
+use std::collections::HashMap;
+use std::path::Path;
+
+pub fn assemble(text: String, dir: &Path) -> Result<Vec<u8>, String> {
+    let (statements, labels) = parse_statements(text, dir)?;
     let mut opcodes: Vec<u8> = vec![];
 
-    for instruction in parse_instructions(text) {
-        let args = instruction.args;
-        let opcode  = match instruction.name.as_str() {
-            "CLS" => 0x00E0,
-            "RET" => 0x00EE,
-            "JP" => 0x1000 + args[0],
-            "CALL" => 0x2000 + args[0],
-            "SEB" => 0x3000 + args[0] * 0x100 + args[1],
-            "SNEB" => 0x4000 + args[0] * 0x100 + args[1],
-            "SE" => 0x5000 + args[0] * 0x100 + args[1] * 0x10,
-            "LDB" => 0x6000 + args[0] * 0x100 + args[1],
-            "ADDB" => 0x7000 + args[0] * 0x100 + args[1],
-            "LD" => 0x8000 + args[0] * 0x100 + args[1] * 0x10,
-            "OR" => 0x8000 + args[0] * 0x100 + args[1] * 0x10 + 0x1,
-            "AND" => 0x8000 + args[0] * 0x100 + args[1] * 0x10 + 0x2,
-            "XOR" => 0x8000 + args[0] * 0x100 + args[1] * 0x10 + 0x3,
-            "ADD" => 0x8000 + args[0] * 0x100 + args[1] * 0x10 + 0x4,
-            "SUB" => 0x8000 + args[0] * 0x100 + args[1] * 0x10 + 0x5,
-            "SHR" => 0x8000 + args[0] * 0x100 + args[1] * 0x10 + 0x6,
-            "SUBN" => 0x8000 + args[0] * 0x100 + args[1] * 0x10 + 0x7,
-            "SHL" => 0x8000 + args[0] * 0x100 + args[1] * 0x10 + 0xE,
-            "SNE" => 0x9000 + args[0] * 0x100 + args[1] * 0x10,
-            "LDI" => 0xA000 + args[0],
-            "JPP" => 0xB000 + args[0],
-            "RND" => 0xC000 + args[0] * 0x100 + args[1],
-            "DRW" => 0xD000 + args[0] * 0x100 + args[1] * 0x10 + args[2],
-            "SKP" => 0xE000 + args[0] * 0x100 + 0x9E,
-            "SKNP" => 0xE000 + args[0] * 0x100 + 0xA1,
-            "GDT" => 0xF000 + args[0] * 0x100 + 0x07,
-            "GK" => 0xF000 + args[0] * 0x100 + 0x0A,
-            "SDT" => 0xF000 + args[0] * 0x100 + 0x15,
-            "SST" => 0xF000 + args[0] * 0x100 + 0x18,
-            "ADDI" => 0xF000 + args[0] * 0x100 + 0x1E,
-            "FONT" => 0xF000 + args[0] * 0x100 + 0x29,
-            "BCD" => 0xF000 + args[0] * 0x100 + 0x33,
-            "LOAD" => 0xF000 + args[0] * 0x100 + 055,
-            "DUMP" => 0xF000 + args[0] * 0x100 + 0x65,
-            _ => 0x0000
+    for statement in statements {
+        let instruction = match statement {
+            Statement::Instruction(instruction) => instruction,
+            Statement::Data(bytes) => {
+                opcodes.extend(bytes);
+                continue;
+            }
         };
+
+        let line = instruction.line;
+        let name = instruction.name.as_str();
+        let mnemonic = match mnemonics::find(name) {
+            Some(mnemonic) if mnemonic.fields.len() == instruction.args.len() => mnemonic,
+            Some(mnemonic) => return Err(format!("line {}: {} takes {} arguments", line, name, mnemonic.fields.len())),
+            None => return Err(format!("line {}: unknown instruction {}", line, name))
+        };
+
+        let mut args = vec![];
+        for (arg, field) in instruction.args.iter().zip(mnemonic.fields) {
+            args.push(resolve(arg, field, &labels).map_err(|e| format!("line {}: {}", line, e))?);
+        }
+
+        let opcode = mnemonic.encode(&args);
         opcodes.push((opcode >> 8) as u8);
         opcodes.push(opcode as u8);
     }
 
-    opcodes
+    Ok(opcodes)
+}
+
+fn resolve(arg: &str, field: &Field, labels: &HashMap<String, u16>) -> Result<u16, String> {
+    let value = arg.parse::<u16>().ok().or_else(|| labels.get(arg).cloned())
+        .ok_or(format!("bad argument {}", arg))?;
+    if value > field.max {
+        return Err(format!("{} is out of range, the largest {} is {}", arg, field.kind, field.max));
+    }
+    Ok(value)
+}
+
+enum Statement {
+    Instruction(Instruction),
+    Data(Vec<u8>)
 }
 
 struct Instruction {
     name: String,
-    args: Vec<u16>
+    args: Vec<String>,
+    line: usize
 }
 
-fn parse_instructions(text: String) -> Vec<Instruction> {
-    text
-        .lines()
-        .filter(|x| !x.is_empty())
-        .map(|x| parse_instruction(x.to_string()))
-        .collect()
+// Sprites get a label with the address they are loaded at, so LDI can point at them
+fn parse_statements(text: String, dir: &Path) -> Result<(Vec<Statement>, HashMap<String, u16>), String> {
+    let mut statements = vec![];
+    let mut labels = HashMap::new();
+    let mut address = 0x200_u16;
+    let mut lines = text.lines().enumerate().map(|(i, x)| (i + 1, strip_comment(x)));
+
+    while let Some((line, text)) = lines.next() {
+        if text.is_empty() {
+            continue;
+        }
+
+        let directive = text.split_whitespace().next().unwrap_or("");
+        let mut statement = if directive == ".sprite" {
+            let mut rows = vec![];
+            loop {
+                match lines.next() {
+                    Some((_, ".end")) => break,
+                    Some((_, "")) => (),
+                    Some((_, row)) => rows.push(row.to_string()),
+                    None => return Err(format!("line {}: .sprite without .end", line))
+                }
+            }
+            Statement::Data(parse_sprite(&rows).map_err(|e| format!("line {}: {}", line, e))?)
+        } else if directive == ".import" {
+            Statement::Data(import_sprite(&text[directive.len()..], dir).map_err(|e| format!("line {}: {}", line, e))?)
+        } else {
+            Statement::Instruction(parse_instruction(text.to_string(), line))
+        };
+
+        if let Statement::Data(ref mut bytes) = statement {
+            // an odd length gets a zero after it, so the instructions that follow stay on even addresses
+            if bytes.len() % 2 == 1 {
+                bytes.push(0);
+            }
+            let label = text.split_whitespace().nth(1).unwrap_or("");
+            if !label.is_empty() && labels.insert(label.to_string(), address).is_some() {
+                return Err(format!("line {}: label {} defined twice", line, label));
+            }
+            address += bytes.len() as u16;
+        } else {
+            address += 2;
+        }
+
+        statements.push(statement);
+    }
+
+    Ok((statements, labels))
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find(';') {
+        Some(index) => line[..index].trim(),
+        None => line.trim()
+    }
 }
 
-fn parse_instruction(line: String) -> Instruction {
+fn parse_instruction(line: String, number: usize) -> Instruction {
     match line.find(' ') {
         Some(index) => {
             let (left, right) = line.split_at(index);
             let name = left.to_string();
-            let args = right.split(",").map( |x| x.trim().parse::<u16>().unwrap() ).collect();
+            let args = right.split(',').map(|x| x.trim().to_string()).collect();
             Instruction {
                 name,
-                args
+                args,
+                line: number
             }
         },
         None => {
             Instruction {
                 name: line,
-                args: vec![]
+                args: vec![],
+                line: number
             }
         }
-    }    
+    }
+}
+
+// .sprite name
+// ..####..
+// .end
+// Rows are 8 pixels wide for DXYN or 16 wide for the 16x16 SCHIP sprite drawn by DXY0
+fn parse_sprite(rows: &[String]) -> Result<Vec<u8>, String> {
+    let width = rows.first().map(|row| row.len()).unwrap_or(0);
+    let mut pixels = vec![];
+
+    for row in rows {
+        if row.len() != width {
+            return Err("sprite rows must all have the same width".to_string());
+        }
+        for c in row.chars() {
+            match c {
+                '#' => pixels.push(true),
+                '.' => pixels.push(false),
+                _ => return Err(format!("unexpected {} in sprite, use # and .", c))
+            }
+        }
+    }
+
+    sprite_bytes(&pixels, width, rows.len())
+}
+
+// .import name path/to/file.png, x, y, width, height
+// The region is optional, dark opaque pixels are set
+fn import_sprite(args: &str, dir: &Path) -> Result<Vec<u8>, String> {
+    let args = args.trim();
+    let rest = args.find(' ').map(|index| &args[index..]).ok_or("missing file name")?;
+    let mut parts = rest.split(',').map(|x| x.trim());
+    let file = parts.next().unwrap_or("").trim_matches('"');
+    let region = parts.map(|x| x.parse::<u32>().map_err(|_| format!("bad region {}", x)))
+        .collect::<Result<Vec<u32>, String>>()?;
+
+    let image = image::open(dir.join(file)).map_err(|e| format!("{}: {}", file, e))?.to_luma_alpha8();
+    let (x, y, width, height) = match region.as_slice() {
+        [] => (0, 0, image.width(), image.height()),
+        [x, y, width, height] => (*x, *y, *width, *height),
+        _ => return Err("the region is x, y, width, height".to_string())
+    };
+
+    if x + width > image.width() || y + height > image.height() {
+        return Err(format!("region is outside the {}x{} image", image.width(), image.height()));
+    }
+
+    // The PBM decoder hands back 0 for black and 1 for white instead of a full luma range
+    let bitmap = file.to_lowercase().ends_with(".pbm");
+    let mut pixels = vec![];
+    for row in y..y + height {
+        for column in x..x + width {
+            let pixel = image.get_pixel(column, row);
+            pixels.push(if bitmap { pixel[0] == 0 } else { pixel[0] < 128 && pixel[1] >= 128 });
+        }
+    }
+
+    sprite_bytes(&pixels, width as usize, height as usize)
+}
+
+fn sprite_bytes(pixels: &[bool], width: usize, height: usize) -> Result<Vec<u8>, String> {
+    match width {
+        8 if height == 0 || height > 15 => {
+            return Err(format!("8 pixel wide sprites have 1 to 15 rows for DXYN, not {}", height))
+        },
+        16 if height != 16 => {
+            return Err(format!("16 pixel wide sprites have 16 rows for DXY0, not {}", height))
+        },
+        8 | 16 => (),
+        _ => return Err(format!("sprites are 8 or 16 pixels wide, not {}", width))
+    }
+
+    Ok(pixels
+        .chunks(8)
+        .map(|bits| bits.iter().fold(0, |byte, &bit| byte << 1 | bit as u8))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assemble;
+    use dissasembler::decode;
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn assembled(text: &str) -> Result<Vec<u8>, String> {
+        assemble(text.to_string(), Path::new("."))
+    }
+
+    // A directory of its own for each test that imports, since tests run at the same time
+    fn directory(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("chip8-assembler-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn eight_wide_rows_are_one_byte_each() {
+        let bytes = assembled(".sprite ship\n#......#\n.######.\n\n..####..\n#......#\n.end");
+        assert_eq!(bytes, Ok(vec![0x81, 0x7E, 0x3C, 0x81]));
+    }
+
+    #[test]
+    fn sixteen_wide_rows_are_two_bytes_each() {
+        let row = "#..............#\n";
+        let bytes = assembled(&format!(".sprite big\n{}.end", row.repeat(16))).unwrap();
+        assert_eq!(bytes, [0x80, 0x01].repeat(16));
+    }
+
+    #[test]
+    fn sprites_are_labelled_with_their_address() {
+        // LDI is at 200, the sprite right after it at 202
+        let bytes = assembled("LDI dot\n.sprite dot\n#.......\n.end");
+        assert_eq!(bytes, Ok(vec![0xA2, 0x02, 0x80, 0x00]));
+    }
+
+    #[test]
+    fn an_odd_sprite_is_padded_so_the_code_after_it_stays_aligned() {
+        // the 3 rows take 4 bytes and the 1 row 2, so the JP 518 loop is at 206
+        let bytes = assembled(".sprite ship\n#......#\n.######.\n..####..\n.end\n.sprite dot\n#.......\n.end\nJP 518");
+        assert_eq!(bytes, Ok(vec![0x81, 0x7E, 0x3C, 0x00, 0x80, 0x00, 0x12, 0x06]));
+        let bytes = assembled(".sprite ship\n#......#\n.######.\n..####..\n.end\nLDI ship\nLDI next\n.sprite next\n#.......\n.end").unwrap();
+        assert_eq!(bytes[4..8], [0xA2, 0x00, 0xA2, 0x08]);
+    }
+
+    #[test]
+    fn sprites_taller_than_dxyn_draws_are_rejected() {
+        let error = assembled(&format!(".sprite tall\n{}.end", "########\n".repeat(16))).unwrap_err();
+        assert!(error.contains("1 to 15 rows"), "{}", error);
+        assert!(assembled(&format!(".sprite tall\n{}.end", "########\n".repeat(15))).is_ok());
+    }
+
+    #[test]
+    fn odd_widths_are_rejected() {
+        let error = assembled(".sprite odd\n#.#.#\n.end").unwrap_err();
+        assert!(error.contains("8 or 16 pixels wide"), "{}", error);
+        assert!(assembled(".sprite ragged\n#.......\n#......\n.end").is_err());
+    }
+
+    #[test]
+    fn directives_are_whole_words() {
+        let error = assembled(".spritefoo\n#.......\n.end").unwrap_err();
+        assert!(error.contains("unknown instruction .spritefoo"), "{}", error);
+        assert!(assembled(".importfoo x.pbm").is_err());
+    }
+
+    #[test]
+    fn a_region_of_a_plain_pbm_is_imported() {
+        let dir = directory("p1");
+        // 10x2, the region leaves out the first and the last column
+        fs::write(dir.join("art.pbm"), "P1\n10 2\n0 1 0 0 0 0 0 0 1 1\n1 0 1 1 1 1 1 1 1 1\n").unwrap();
+        let bytes = assemble(".import art art.pbm, 1, 0, 8, 2".to_string(), &dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(bytes, Ok(vec![0x81, 0x7F]));
+    }
+
+    #[test]
+    fn a_region_of_a_raw_pbm_is_imported() {
+        let dir = directory("p4");
+        // 16x2, each row padded to two bytes
+        let mut file = b"P4\n16 2\n".to_vec();
+        file.extend(&[0xF0, 0x0F, 0xAA, 0x55]);
+        fs::write(dir.join("art.pbm"), file).unwrap();
+        let bytes = assemble(".import art art.pbm, 4, 0, 8, 2".to_string(), &dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(bytes, Ok(vec![0x00, 0xA5]));
+    }
+
+    #[test]
+    fn disassembled_programs_assemble_back_to_themselves() {
+        let text = "CLS\nLDB 1, 255\nDUMP 3\nLOAD 3\nDRW 1, 2, 5\nSKNP 4\nSHL 1, 2\nJP 512\nRET";
+        let bytes = assembled(text).unwrap();
+        assert_eq!(bytes[4..8], [0xF3, 0x55, 0xF3, 0x65]);
+
+        let decoded: Vec<String> = bytes.chunks(2).map(|pair| decode(pair[0], pair[1])).collect();
+        assert_eq!(decoded.join("\n"), text);
+    }
 }
@@ -1,52 +1,106 @@
 extern crate rand;
 
-use cpu::{Cpu, Hack};
+use cpu::{self, Cpu, Hack};
 use cpu::Message;
 use data;
 use dissasembler::decode;
-use hardware::Hardware;
-use piston_window::*;
+use frontend::Event;
+use hardware::{Control, Hardware, HEIGHT, WIDTH};
 use std::fs::File;
-use std::io::Read;
 use std::io::Write;
 use std::string::String;
 
 pub struct Chip8 {
     pub cpu: Cpu,
     pub hardware: Hardware,
-    pub log: File
+    // the register FX0A stores the key in
+    pub key_vx: u8,
+    // instruction trace, written only when set
+    pub log: Option<File>,
+    // every instruction run so far, for the instructions per second
+    pub instructions: u64,
+    // the address and opcode of the last instruction the CPU does not know, until taken
+    pub unknown: Option<(u16, u16)>
 }
 
 impl Chip8 {
-    pub fn new(path: &str, hack: Hack) -> Chip8 {
+    pub fn new(rom: &[u8], hack: Hack) -> Chip8 {
         let mut chip8 = Chip8 {
             cpu: Cpu::new(hack),
             hardware: Hardware {
-                gfx: [[0; 64]; 32],
-                keys: [false; 17]
+                gfx: [[0; WIDTH]; HEIGHT],
+                keys: [false; 16],
+                bindings: data::layout("qwerty").unwrap_or_default(),
+                held: vec![]
             },
-            log: File::create("dev/log.txt").unwrap()
+            key_vx: 0,
+            log: None,
+            instructions: 0,
+            unknown: None
         };
-        
-        let fontset = data::fontset();
-        for i in 0..80 {
-            chip8.cpu.memory[i] = fontset[i];
-        }
+        chip8.load(rom);
+        chip8
+    }
 
-        let rom = File::open(path).unwrap().bytes().take(3896);
-        for (i, maybe_byte) in rom.enumerate() {
-            chip8.cpu.memory[i + 512] = maybe_byte.unwrap();
-        }
+    fn load(&mut self, rom: &[u8]) {
+        self.cpu.memory[..80].copy_from_slice(&data::fontset());
 
-        chip8
+        let size = rom.len().min(3584);
+        self.cpu.memory[512..512 + size].copy_from_slice(&rom[..size]);
+    }
+
+    // Starts the program again with the registers, timers, stack, screen and keys cleared,
+    // the memory stays as the program left it
+    #[cfg_attr(not(any(feature = "piston", feature = "terminal")), allow(dead_code))]
+    pub fn reset(&mut self) {
+        let memory = self.cpu.memory;
+        self.cpu = Cpu::new(self.cpu.hack);
+        self.cpu.memory = memory;
+        self.hardware.clear();
+        self.hardware.keys = [false; 16];
+        self.hardware.held.clear();
+        self.key_vx = 0;
+    }
+
+    // A reset with the memory as it was when the ROM was loaded
+    #[cfg_attr(not(any(feature = "piston", feature = "terminal")), allow(dead_code))]
+    pub fn reload(&mut self, rom: &[u8]) {
+        self.reset();
+        self.cpu.memory = [0; cpu::MEMORY];
+        self.load(rom);
+    }
+
+    // One 60 Hz frame: the timers tick, then up to ipf instructions run
+    // unless FX0A is waiting for a key. Returns whether the screen changed
+    pub fn frame(&mut self, ipf: u32) -> bool {
+        self.cpu.tick();
+        let mut drawn = false;
+        for _ in 0..ipf {
+            if self.cpu.halted || self.cpu.fault.is_some() {
+                break;
+            }
+            if self.log.is_some() {
+                self.debug();
+            }
+            self.instructions += 1;
+            match self.step() {
+                Message::Clear | Message::Draw(_, _, _) => drawn = true,
+                _ => ()
+            }
+        }
+        drawn
     }
 
     pub fn step(&mut self) -> Message {
         use cpu::Message::*;
+        let (byte1, byte2) = self.cpu.opcode();
+        if !cpu::known(byte1, byte2) {
+            self.unknown = Some((self.cpu.pc, (byte1 as u16) << 8 | byte2 as u16));
+        }
         match self.cpu.step() {                          
             Clear => {
                 self.hardware.clear();
-                NoMessage
+                Clear
             },
             Draw(bits2, bits3, bits4) => {
                 self.draw(bits2, bits3, bits4);
@@ -58,6 +112,7 @@ impl Chip8 {
             },
             GetKey(bits2) => {                        
                 self.cpu.halted = true;
+                self.key_vx = bits2;
                 GetKey(bits2)
             },
             NoMessage => NoMessage
@@ -78,11 +133,11 @@ impl Chip8 {
         let height = bits4;
 
         for relative_y in 0..height {
-            let index_y = (y as usize + relative_y as usize) % 32;
-            let byte = self.cpu.memory[(self.cpu.i + relative_y as u16) as usize];
+            let index_y = (y as usize + relative_y as usize) % HEIGHT;
+            let byte = self.cpu.memory[(self.cpu.i as usize + relative_y as usize) % cpu::MEMORY];
             for relative_x in 0..8 {
-                let index_x = (x as usize + relative_x as usize) % 64;                
-                if index_x < 64 && index_y < 32 {
+                let index_x = (x as usize + relative_x as usize) % WIDTH;                
+                if index_x < WIDTH && index_y < HEIGHT {
                     let prev = self.hardware.gfx[index_y][index_x];
                     let next = prev ^ (byte << relative_x & 128) >> 7;
                     if prev == 1 && next == 0 {
@@ -95,64 +150,241 @@ impl Chip8 {
         self.cpu.v[15] = flipped;
     }
     
-    pub fn hardware_key(&mut self, button_args: ButtonArgs, key_vx: u8) {
-        if let ButtonArgs { button: Button::Keyboard(key), state, .. } = button_args {
-            match state {
-                ButtonState::Press => {
-                    self.hardware.press(key);
-                    if self.cpu.halted {
-                        self.cpu.v[key_vx as usize] = data::key_to_number(key) as u8;
-                        self.cpu.halted = false;
-                    }
-                },
-                ButtonState::Release => {
-                    self.hardware.release(key);                    
-                }
+    // A control held or let go, or one of the 16 keys pressed or released itself
+    pub fn event(&mut self, event: Event) {
+        match event {
+            Event::Control(control, held) => self.control(control, held),
+            Event::Keypad(number, pressed) => self.control(Control::Keypad(number), pressed)
+        }
+    }
+
+    // Holds or lets go of a control, repeats and releases of controls not held do nothing
+    fn control(&mut self, control: Control, held: bool) {
+        let number = match self.hardware.key_number(control) {
+            Some(number) => number,
+            None => return
+        };
+
+        if held {
+            if !self.hardware.held.contains(&control) {
+                self.hardware.held.push(control);
+                self.keypad(number, true);
             }
-        }        
+        } else if self.hardware.held.contains(&control) {
+            self.hardware.held.retain(|&x| x != control);
+            let hardware = &self.hardware;
+            if !hardware.held.iter().any(|&x| hardware.key_number(x) == Some(number)) {
+                self.keypad(number, false);
+            }
+        }
+    }
+
+    // Presses or releases one of the 16 keys, a press ends the wait of FX0A
+    fn keypad(&mut self, number: usize, pressed: bool) {
+        if pressed {
+            self.hardware.press(number);
+            if self.cpu.halted {
+                self.cpu.v[self.key_vx as usize] = number as u8;
+                self.cpu.halted = false;
+            }
+        } else {
+            self.hardware.release(number);
+        }
     }
     
     pub fn key(&mut self, bits2: u8, bits3: u8, bits4: u8) {
         let two_last = bits3 << 4 | bits4;
-        let vx = self.cpu.v[bits2 as usize] as usize;
+        let vx = (self.cpu.v[bits2 as usize] & 15) as usize;
         
         match two_last {
             // EX9E 	KeyOp 	if(key()==Vx)
             // Skips the next instruction if the key stored in VX is pressed.
             // (Usually the next instruction is a jump to skip a code block)
-            0x9E => {
-                if self.hardware.keys[vx] {                    
-                    self.cpu.pc += 2;
-                }
-            },
+            0x9E if self.hardware.keys[vx] => self.cpu.pc += 2,
 
             // EXA1 	KeyOp 	if(key()!=Vx)
             // Skips the next instruction if the key stored in VX isn't pressed.
             // (Usually the next instruction is a jump to skip a code block)
-            0xA1 => {
-                if !self.hardware.keys[vx] {
-                    self.cpu.pc += 2;                    
-                }
-            },
+            0xA1 if !self.hardware.keys[vx] => self.cpu.pc += 2,
             _ => ()
         }
     }
 
     pub fn debug(&mut self) {
-        let pc = self.cpu.pc as usize;
-        let byte1 = self.cpu.memory[pc];
-        let byte2 = self.cpu.memory[pc + 1];
+        let (byte1, byte2) = self.cpu.opcode();
         let opcode = (byte1 as u16) << 8 | byte2 as u16;
         let v = (0..16).map(|x| format!("{:<2x} ", self.cpu.v[x])).collect::<String>();
 
-        let _ = write!(
-            &mut self.log,            
-            "{:<16} {:<4x} {} p{:<4x} i{:<4x}\n",
-            decode(byte1, byte2),
-            opcode,
-            v,
-            self.cpu.pc,
-            self.cpu.i
-        );
+        if let Some(ref mut log) = self.log {
+            let _ = writeln!(
+                log,
+                "{:<16} {:<4x} {} p{:<4x} i{:<4x}",
+                decode(byte1, byte2),
+                opcode,
+                v,
+                self.cpu.pc,
+                self.cpu.i
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Chip8;
+    use cpu::Hack;
+    use frontend::Event;
+    use hardware::{Control, Direction};
+    use key::Key;
+
+    const W: Control = Control::Key(Key::W);
+    const UP: Control = Control::Key(Key::Up);
+    const A: Control = Control::Button(0);
+    const HAT_UP: Control = Control::Hat(Direction::Up);
+    const STICK_UP: Control = Control::Axis(1, false);
+
+    // W and the arrow up on 5, like Pong's two players, and a pad on 2
+    fn chip8() -> Chip8 {
+        let mut chip8 = Chip8::new(&[], Hack { shift_hack: false, memory_hack: false });
+        chip8.hardware.bindings = vec![(W, 5), (UP, 5), (A, 5), (HAT_UP, 2), (STICK_UP, 2)];
+        chip8
+    }
+
+    fn send(chip8: &mut Chip8, events: &[(Control, bool)]) {
+        for &(control, held) in events {
+            chip8.event(Event::Control(control, held));
+        }
+    }
+
+    #[test]
+    fn each_kind_of_control_presses_and_releases_its_key() {
+        for &(control, number) in &[(W, 5), (A, 5), (HAT_UP, 2), (STICK_UP, 2)] {
+            let mut chip8 = chip8();
+            send(&mut chip8, &[(control, true)]);
+            assert!(chip8.hardware.keys[number], "{:?} presses {}", control, number);
+            send(&mut chip8, &[(control, false)]);
+            assert!(!chip8.hardware.keys[number], "{:?} releases {}", control, number);
+        }
+    }
+
+    #[test]
+    fn a_key_stays_pressed_while_any_of_its_controls_is_held() {
+        let mut chip8 = chip8();
+        send(&mut chip8, &[(W, true), (UP, true), (W, false)]);
+        assert!(chip8.hardware.keys[5]);
+        send(&mut chip8, &[(UP, false)]);
+        assert!(!chip8.hardware.keys[5]);
+
+        send(&mut chip8, &[(HAT_UP, true), (STICK_UP, true), (STICK_UP, false)]);
+        assert!(chip8.hardware.keys[2]);
+        send(&mut chip8, &[(HAT_UP, false)]);
+        assert!(!chip8.hardware.keys[2]);
+    }
+
+    #[test]
+    fn repeats_and_stray_releases_do_nothing() {
+        let mut chip8 = chip8();
+        send(&mut chip8, &[(W, true), (W, true), (UP, false)]);
+        assert!(chip8.hardware.keys[5]);
+        assert_eq!(chip8.hardware.held, vec![W]);
+        send(&mut chip8, &[(W, false)]);
+        assert!(!chip8.hardware.keys[5]);
+        assert!(chip8.hardware.held.is_empty());
+    }
+
+    #[test]
+    fn unbound_controls_press_nothing() {
+        let mut chip8 = chip8();
+        send(&mut chip8, &[(Control::Key(Key::Z), true), (Control::Button(7), true)]);
+        assert_eq!(chip8.hardware.keys, [false; 16]);
+        assert!(chip8.hardware.held.is_empty());
+    }
+
+    #[test]
+    fn the_pointer_holds_a_key_like_any_control() {
+        let mut chip8 = chip8();
+        chip8.event(Event::Keypad(5, true));
+        send(&mut chip8, &[(W, true)]);
+        chip8.event(Event::Keypad(5, false));
+        assert!(chip8.hardware.keys[5]);
+        send(&mut chip8, &[(W, false)]);
+        assert!(!chip8.hardware.keys[5]);
+
+        send(&mut chip8, &[(W, true)]);
+        chip8.event(Event::Keypad(5, true));
+        send(&mut chip8, &[(W, false)]);
+        assert!(chip8.hardware.keys[5]);
+        chip8.event(Event::Keypad(5, false));
+        assert!(!chip8.hardware.keys[5]);
+    }
+
+    #[test]
+    fn a_press_ends_the_wait_for_a_key() {
+        let mut chip8 = chip8();
+        chip8.cpu.halted = true;
+        chip8.key_vx = 3;
+        send(&mut chip8, &[(HAT_UP, true)]);
+        assert!(!chip8.cpu.halted);
+        assert_eq!(chip8.cpu.v[3], 2);
+    }
+
+    #[test]
+    fn a_reset_keeps_the_memory_and_a_reload_loads_the_rom_again() {
+        // LDB 0, 7; LDI 300; DUMP 0; JP 206
+        let rom = [0x60, 0x07, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06];
+        let mut chip8 = Chip8::new(&rom, Hack { shift_hack: false, memory_hack: false });
+        chip8.frame(10);
+        send(&mut chip8, &[(Control::Keypad(5), true)]);
+        chip8.hardware.gfx[0][0] = 1;
+
+        chip8.reset();
+        assert_eq!(chip8.cpu.v[0], 0);
+        assert_eq!(chip8.cpu.pc, 0x200);
+        assert_eq!(chip8.cpu.memory[0x300], 7);
+        assert_eq!(chip8.hardware.gfx[0][0], 0);
+        assert_eq!(chip8.hardware.keys, [false; 16]);
+
+        chip8.reload(&rom);
+        assert_eq!(chip8.cpu.memory[0x300], 0);
+        assert_eq!(chip8.cpu.memory[0x200..0x208], rom);
+    }
+
+    #[test]
+    fn memory_wraps_around_at_its_end() {
+        let mut chip8 = chip8();
+        // LDB 0, 5 split over the last two bytes, the font is next
+        chip8.cpu.memory[0xFFE..].copy_from_slice(&[0x60, 0x05]);
+        chip8.cpu.pc = 0xFFE;
+        chip8.step();
+        assert_eq!((chip8.cpu.v[0], chip8.cpu.pc), (5, 0));
+
+        // a sprite from the last byte on takes the top of the 0 next
+        chip8.cpu.memory[0xFFF] = 0x80;
+        chip8.cpu.i = 0xFFF;
+        chip8.draw(1, 1, 2);
+        assert_eq!(chip8.hardware.gfx[0][..5], [1, 0, 0, 0, 0]);
+        assert_eq!(chip8.hardware.gfx[1][..5], [1, 1, 1, 1, 0]);
+
+        // DUMP 2; LOAD 2 from the same place
+        chip8.cpu.v[..3].copy_from_slice(&[7, 8, 9]);
+        chip8.cpu.i = 0xFFE;
+        chip8.cpu.memory[..2].copy_from_slice(&[0xF2, 0x55]);
+        chip8.cpu.pc = 0;
+        chip8.step();
+        assert_eq!(chip8.cpu.memory[0xFFE..], [7, 8]);
+        assert_eq!(chip8.cpu.memory[0], 9);
+        assert_eq!(chip8.cpu.i, 1);
+        chip8.cpu.i = 0xFFE;
+        chip8.cpu.v[..3].copy_from_slice(&[0, 0, 0]);
+        chip8.cpu.memory[2..4].copy_from_slice(&[0xF2, 0x65]);
+        chip8.step();
+        assert_eq!(chip8.cpu.v[..3], [7, 8, 9]);
+
+        // BCD 0 from the last byte
+        chip8.cpu.i = 0xFFF;
+        chip8.cpu.v[0] = 125;
+        chip8.cpu.memory[4..6].copy_from_slice(&[0xF0, 0x33]);
+        chip8.step();
+        assert_eq!((chip8.cpu.memory[0xFFF], chip8.cpu.memory[0], chip8.cpu.memory[1]), (1, 2, 5));
     }
 }
@@ -0,0 +1,601 @@
+use analyzer;
+use assembler::assemble;
+use chip8::Chip8;
+use config::Config;
+use data;
+use database::{self, Database, Quirks};
+use dissasembler::disassemble;
+use effects::{self, Effect};
+use flicker::{Filter, Flicker};
+use frontend::Nothing;
+use headless::{self, Script};
+use library;
+#[cfg(feature = "piston")]
+use menu::Menu;
+use orientation::Orientation;
+use recorder::Recorder;
+#[cfg(feature = "piston")]
+use run;
+use screenshot;
+#[cfg(feature = "terminal")]
+use terminal;
+use settings::{Overrides, Settings};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use upscale::{self, Upscaler};
+use video;
+#[cfg(feature = "piston")]
+use window::Window;
+
+const USAGE: &str = "usage: chip8 [<command>] [<args>]
+
+With no command, opens a window to pick a game of roms/roms.json.
+
+commands:
+    run <game>        play a game, or run it headless with --frames
+    play <game>       same as run
+    debug <game>      run with an instruction trace
+    asm [<file>]      assemble a source file
+    disasm <rom>      disassemble a ROM
+    analyze <rom>     look for problems and quirk-sensitive instructions
+    test-roms         run every game of the database headless
+    list              list the games of the database
+    search <text>     search titles and descriptions
+    info <game>       show the settings and description of a game
+    validate          check the database against the ROM files
+    config            show where the config file is and check it
+
+<game> is an index from list, a title or a path to a ROM.
+chip8 <command> --help shows the options of a command.
+
+The config file sets the defaults and per game settings, see the README.
+Gamepads need a build with the gamepad feature: cargo build --features gamepad
+
+exit codes: 0 success, 1 failure, 2 bad usage";
+
+const RUN_USAGE: &str = "usage: chip8 run <game> [options]
+
+options:
+    --quirks <list>       shift and loadStore separated by commas, or none
+    --ipf <n>             instructions per frame
+    --scale <n>           pixels per CHIP-8 pixel up to 64, for screenshots and the first size
+                          of the window, which then remembers its size
+    --fractional          let the window scale by any factor, not only whole ones
+    --palette <name>      blue, classic, green, amber, lcd, high-contrast, colorblind,
+                          octo or one of the config, F2 cycles through them
+    --gap <n>             pixels of background between CHIP-8 pixels, a tenth of the scale by default
+                          with the nearest upscaler and none with the others
+    --grid                fill the gaps with a faint grid
+    --upscaler <name>     nearest, scale2x, scale3x, hqx or lcd
+    --effects <list>      scanlines, mask, bloom and led separated by commas, or none,
+                          F4 switches them off and on
+    --rotate <degrees>    turn the screen clockwise by 90, 180 or 270 degrees
+    --flip <direction>    mirror the screen, horizontal, vertical or off
+    --turbo <n>           how many times faster holding Tab runs, 0 for as fast as it can
+    --flicker <mode>      off, blend:<frames>, decay:<milliseconds> or stable:<frames>
+    --layout <name>       keyboard layout: qwerty, azerty, qwertz, dvorak or keypad
+    --keypad              show a clickable keypad next to the screen, F1 toggles it
+    --status              show frames and instructions per second, the title and the sound
+                          under the screen, F3 toggles it
+    --terminal            play in the terminal instead of a window, over SSH or without a GPU
+    --braille             draw the terminal screen in braille dots instead of half blocks
+    --frames <n>          run headless for n frames, 60 per second, and exit
+    --input <file>        headless input, lines of <frame> press|release <hex key or name>
+    --screenshot <file>   save the screen once the frames have run, at --scale or 1x, as PNG,
+                          PPM or as PBM with the bare 64x32 pixels
+    --record <file>       record the frames to an animated GIF at --scale or 1x";
+
+const DEBUG_USAGE: &str = "usage: chip8 debug <game> [options]
+
+Runs like chip8 run, writing every instruction with the registers to a log.
+
+options:
+    --log <file>          where the trace goes, dev/log.txt by default
+    and the options of chip8 run";
+
+const ASM_USAGE: &str = "usage: chip8 asm [<file>] [-o <out>]
+
+Assembles <file>, dev/rom.asm by default, into <out>, the same name with
+a .ch8 extension by default. Imported images are relative to <file>.";
+
+const DISASM_USAGE: &str = "usage: chip8 disasm <rom> [-o <out>]
+
+Prints the address, opcode and instruction of every two bytes of <rom>.";
+
+const ANALYZE_USAGE: &str = "usage: chip8 analyze <rom>
+
+Exits with 1 if the reachable code has invalid opcodes, jumps outside
+the program or returns with an empty stack.";
+
+const TEST_ROMS_USAGE: &str = "usage: chip8 test-roms [--frames <n>]
+
+Runs every game of the database headless, 600 frames by default, and
+reports the ones that crash or never draw. Exits with 1 if any crashed.";
+
+const LIST_USAGE: &str = "usage: chip8 list";
+const SEARCH_USAGE: &str = "usage: chip8 search <text>";
+const INFO_USAGE: &str = "usage: chip8 info <game>";
+const VALIDATE_USAGE: &str = "usage: chip8 validate";
+const CONFIG_USAGE: &str = "usage: chip8 config
+
+The config is read from $CHIP8_CONFIG or else chip8/config.json in the
+user's config directory.";
+
+pub enum Failure {
+    Usage(String),
+    Error(String)
+}
+
+impl From<String> for Failure {
+    fn from(error: String) -> Failure {
+        Failure::Error(error)
+    }
+}
+
+// The parsed arguments of a command, options either take a value or are flags
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+    flags: Vec<String>,
+    help: bool
+}
+
+impl Args {
+    fn parse(args: &[String], valued: &[&str], flags: &[&str]) -> Result<Args, Failure> {
+        let mut parsed = Args { positional: vec![], options: vec![], flags: vec![], help: false };
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                parsed.help = true;
+            } else if valued.contains(&arg.as_str()) {
+                match args.next() {
+                    Some(value) => parsed.options.push((arg.clone(), value.clone())),
+                    None => return Err(Failure::Usage(format!("{} expects a value", arg)))
+                }
+            } else if flags.contains(&arg.as_str()) {
+                parsed.flags.push(arg.clone());
+            } else if arg.starts_with('-') && arg.len() > 1 {
+                return Err(Failure::Usage(format!("unknown option {}", arg)));
+            } else {
+                parsed.positional.push(arg.clone());
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|x| x == flag)
+    }
+
+    fn value(&self, option: &str) -> Option<&str> {
+        self.options.iter().rev().find(|x| x.0 == option).map(|x| x.1.as_str())
+    }
+
+    fn number(&self, option: &str) -> Result<Option<u32>, Failure> {
+        match self.value(option) {
+            None => Ok(None),
+            Some(value) => match value.parse::<u32>() {
+                Ok(number) if number > 0 => Ok(Some(number)),
+                _ => Err(Failure::Usage(format!("{} expects a positive number", option)))
+            }
+        }
+    }
+
+    fn positional(&self, index: usize, what: &str) -> Result<&str, Failure> {
+        self.positional.get(index).map(|x| x.as_str()).ok_or_else(|| Failure::Usage(format!("missing {}", what)))
+    }
+}
+
+type Command = fn(&Args) -> Result<(), Failure>;
+
+// Returns the exit code
+pub fn main(args: &[String]) -> i32 {
+    let command = args.get(1).map(|x| x.as_str());
+    let rest = if args.len() > 2 { &args[2..] } else { &[] };
+    // debug takes what run does and a log
+    let debug_options: Vec<&str> = RUN_OPTIONS.iter().cloned().chain(Some("--log")).collect();
+
+    let (usage, valued, flags, function): (&str, &[&str], &[&str], Command) = match command {
+        None => {
+            return match play() {
+                Ok(()) => 0,
+                Err(error) => fail(Failure::Error(error), USAGE)
+            }
+        },
+        Some("--help") | Some("-h") | Some("help") => {
+            println!("{}", USAGE);
+            return 0;
+        },
+        Some("run") | Some("play") => (RUN_USAGE, &RUN_OPTIONS, &RUN_FLAGS, run_command),
+        Some("debug") => (DEBUG_USAGE, &debug_options, &RUN_FLAGS, debug),
+        Some("asm") => (ASM_USAGE, &["-o"], &[], asm),
+        Some("disasm") => (DISASM_USAGE, &["-o"], &[], disasm),
+        Some("analyze") => (ANALYZE_USAGE, &[], &[], analyze),
+        Some("test-roms") => (TEST_ROMS_USAGE, &["--frames"], &[], test_roms),
+        Some("list") => (LIST_USAGE, &[], &[], list),
+        Some("search") => (SEARCH_USAGE, &[], &[], search),
+        Some("info") => (INFO_USAGE, &[], &[], info),
+        Some("validate") => (VALIDATE_USAGE, &[], &[], validate),
+        Some("config") => (CONFIG_USAGE, &[], &[], config),
+        Some(command) => return fail(Failure::Usage(format!("unknown command {}", command)), USAGE)
+    };
+
+    let result = Args::parse(rest, valued, flags).and_then(|args| {
+        if args.help {
+            println!("{}", usage);
+            Ok(())
+        } else {
+            function(&args)
+        }
+    });
+
+    match result {
+        Ok(()) => 0,
+        Err(failure) => fail(failure, usage)
+    }
+}
+
+fn fail(failure: Failure, usage: &str) -> i32 {
+    match failure {
+        Failure::Usage(message) => {
+            eprintln!("{}\n\n{}", message, usage);
+            2
+        },
+        Failure::Error(message) => {
+            eprintln!("{}", message);
+            1
+        }
+    }
+}
+
+const RUN_OPTIONS: [&str; 16] = ["--quirks", "--ipf", "--scale", "--gap", "--upscaler", "--effects", "--rotate", "--flip", "--turbo", "--palette", "--flicker", "--layout", "--frames", "--input", "--screenshot", "--record"];
+const RUN_FLAGS: [&str; 6] = ["--keypad", "--grid", "--fractional", "--status", "--terminal", "--braille"];
+
+fn run_command(args: &Args) -> Result<(), Failure> {
+    start(args, None)
+}
+
+fn debug(args: &Args) -> Result<(), Failure> {
+    start(args, Some(args.value("--log").unwrap_or("dev/log.txt")))
+}
+
+fn start(args: &Args, log: Option<&str>) -> Result<(), Failure> {
+    let database = load_database()?;
+    let rom = library::resolve(&database, args.positional(0, "a game")?)?;
+    let bytes = read(&rom)?;
+    let overrides = overrides(args)?;
+    let config = Config::load()?;
+    if let Some(ref palette) = overrides.palette {
+        let palettes = config.palettes();
+        if !palettes.iter().any(|x| &x.0 == palette) {
+            let names: Vec<&str> = palettes.iter().map(|x| x.0.as_str()).collect();
+            return Err(Failure::Usage(format!("unknown palette {}, use {}", palette, names.join(", "))));
+        }
+    }
+    // the window loads dropped ROMs the same way
+    let load = |bytes: &[u8]| {
+        let settings = Settings::resolve(bytes, &config, &overrides);
+        emulator(bytes, &settings, log).map(|chip8| (chip8, settings))
+    };
+
+    match args.number("--frames")? {
+        Some(frames) => {
+            let (mut chip8, settings) = load(&bytes)?;
+            let mut script = match args.value("--input") {
+                Some(path) => Script::load(path)?,
+                None => Script::empty()
+            };
+            let mut filter = Filter::new(settings.flicker);
+            let options = settings.video(overrides.scale.unwrap_or(1));
+            let mut recorder = match args.value("--record") {
+                Some(path) => Some(Recorder::start(Path::new(path), settings.colors(), options.clone(), 0)?),
+                None => None
+            };
+            headless::run(&mut chip8, settings.ipf, frames, &mut script, &mut recorder, &mut filter)?;
+            if let Some(recorder) = recorder {
+                recorder.finish(frames as u64)?;
+            }
+            if let Some(path) = args.value("--screenshot") {
+                let frame = || video::render(&filter.colors, &filter.levels, settings.colors(), &options);
+                screenshot::save(Path::new(path), &chip8.hardware.gfx, frame)?;
+            }
+            Ok(())
+        },
+        None => {
+            if args.value("--input").is_some() || args.value("--screenshot").is_some() || args.value("--record").is_some() {
+                return Err(Failure::Usage("--input, --screenshot and --record need --frames".to_string()));
+            }
+            let game = load(&bytes)?;
+            if args.flag("--terminal") {
+                return play_terminal(game, bytes, args.flag("--braille"));
+            }
+            play_window(game, bytes, load, overrides.scale.is_none())
+        }
+    }
+}
+
+#[cfg(feature = "terminal")]
+fn play_terminal(game: (Chip8, Settings), rom: Vec<u8>, braille: bool) -> Result<(), Failure> {
+    Ok(terminal::run(game, rom, braille)?)
+}
+
+// Builds without a frontend say so
+#[cfg(not(feature = "terminal"))]
+fn play_terminal(_: (Chip8, Settings), _: Vec<u8>, _: bool) -> Result<(), Failure> {
+    Err(Failure::Error("this build has no terminal frontend, it needs the terminal feature".to_string()))
+}
+
+// The window opens at the size it was left at unless a scale was given
+#[cfg(feature = "piston")]
+fn play_window(game: (Chip8, Settings), rom: Vec<u8>, load: impl Fn(&[u8]) -> Result<(Chip8, Settings), String>,
+               restore_size: bool) -> Result<(), Failure> {
+    let mut window = Window::open(run::size(&game.1), restore_size)?;
+    run::run(&mut window, game, rom, load)?;
+    window.save();
+    Ok(())
+}
+
+#[cfg(not(feature = "piston"))]
+fn play_window(_: (Chip8, Settings), _: Vec<u8>, _: impl Fn(&[u8]) -> Result<(Chip8, Settings), String>,
+               _: bool) -> Result<(), Failure> {
+    Err(Failure::Error("this build has no window, it needs the piston feature".to_string()))
+}
+
+// The trace goes to the log of debug or else to the one of the config
+fn emulator(bytes: &[u8], settings: &Settings, log: Option<&str>) -> Result<Chip8, String> {
+    let mut chip8 = Chip8::new(bytes, settings.hack());
+    chip8.hardware.bindings = settings.bindings.clone();
+    if let Some(log) = log.or(settings.trace.as_deref()) {
+        chip8.log = Some(File::create(log).map_err(|e| format!("{}: {}", log, e))?);
+    }
+    Ok(chip8)
+}
+
+fn overrides(args: &Args) -> Result<Overrides, Failure> {
+    let mut overrides = Overrides::default();
+
+    if let Some(value) = args.value("--quirks") {
+        let mut quirks = Quirks::profile("none").unwrap_or_default();
+        match Quirks::profile(value) {
+            Some(profile) => quirks = profile,
+            None => for name in value.split(',') {
+                match name {
+                    "shift" => quirks.shift = Some(true),
+                    "loadStore" | "load-store" => quirks.load_store = Some(true),
+                    _ => return Err(Failure::Usage(format!("unknown quirk {}, use shift, loadStore, chip8, schip or none", name)))
+                }
+            }
+        }
+        overrides.quirks = Some(quirks);
+    }
+    overrides.ipf = args.number("--ipf")?;
+    overrides.scale = args.number("--scale")?;
+    if let Some(scale) = overrides.scale.filter(|&scale| scale > video::MAX_SCALE) {
+        return Err(Failure::Usage(format!("--scale {} is too big, the most is {}", scale, video::MAX_SCALE)));
+    }
+    if let Some(layout) = args.value("--layout") {
+        if data::layout(layout).is_none() {
+            return Err(Failure::Usage(format!("unknown layout {}, use {}", layout, data::LAYOUTS.join(", "))));
+        }
+        overrides.layout = Some(layout.to_string());
+    }
+    overrides.palette = args.value("--palette").map(|x| x.to_string());
+    if let Some(flicker) = args.value("--flicker") {
+        match Flicker::parse(flicker) {
+            Some(flicker) => overrides.flicker = Some(flicker),
+            None => return Err(Failure::Usage(format!("unknown flicker mode {}", flicker)))
+        }
+    }
+    if args.flag("--keypad") {
+        overrides.keypad = Some(true);
+    }
+    if let Some(gap) = args.value("--gap") {
+        overrides.gap = Some(gap.parse().map_err(|_| Failure::Usage("--gap expects a number".to_string()))?);
+    }
+    if args.flag("--grid") {
+        overrides.grid = Some(true);
+    }
+    if args.flag("--fractional") {
+        overrides.fractional = Some(true);
+    }
+    if args.flag("--status") {
+        overrides.status = Some(true);
+    }
+    if let Some(turbo) = args.value("--turbo") {
+        overrides.turbo = Some(turbo.parse().map_err(|_| Failure::Usage("--turbo expects a number".to_string()))?);
+    }
+    if let Some(rotation) = args.value("--rotate") {
+        match rotation.parse::<u16>() {
+            Ok(rotation) if rotation % 90 == 0 && rotation < 360 => overrides.rotation = Some(rotation),
+            _ => return Err(Failure::Usage("--rotate expects 0, 90, 180 or 270".to_string()))
+        }
+    }
+    if let Some(flip) = args.value("--flip") {
+        match Orientation::parse_flip(flip) {
+            Some(flip) => overrides.flip = Some(flip),
+            None => return Err(Failure::Usage(format!("unknown flip {}, use horizontal, vertical or off", flip)))
+        }
+    }
+    if let Some(name) = args.value("--upscaler") {
+        match Upscaler::parse(name) {
+            Some(upscaler) => overrides.upscaler = Some(upscaler),
+            None => return Err(Failure::Usage(format!("unknown upscaler {}, expected one of {}", name, upscale::NAMES.join(", "))))
+        }
+    }
+    if let Some(list) = args.value("--effects") {
+        let mut effects = vec![];
+        for name in list.split(',').filter(|name| *name != "none") {
+            match Effect::parse(name) {
+                Some(effect) => effects.push(effect),
+                None => return Err(Failure::Usage(format!("unknown effect {}, expected some of {}", name, effects::NAMES.join(", "))))
+            }
+        }
+        overrides.effects = Some(effects);
+    }
+
+    Ok(overrides)
+}
+
+fn asm(args: &Args) -> Result<(), Failure> {
+    let input = Path::new(args.positional.first().map(|x| x.as_str()).unwrap_or("dev/rom.asm"));
+    let output = args.value("-o").map(PathBuf::from).unwrap_or_else(|| input.with_extension("ch8"));
+    let text = fs::read_to_string(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+    let dir = input.parent().unwrap_or_else(|| Path::new(""));
+
+    let bytes = assemble(text, dir).map_err(|e| format!("{}: {}", input.display(), e))?;
+    fs::write(&output, bytes).map_err(|e| format!("{}: {}", output.display(), e))?;
+    Ok(())
+}
+
+fn disasm(args: &Args) -> Result<(), Failure> {
+    let rom = args.positional(0, "a ROM")?;
+    let text = disassemble(read(Path::new(rom))?);
+
+    match args.value("-o") {
+        Some(output) => fs::write(output, text + "\n").map_err(|e| format!("{}: {}", output, e))?,
+        // a closed pipe, like when piped into head, is not an error
+        None => { let _ = writeln!(io::stdout(), "{}", text); }
+    }
+    Ok(())
+}
+
+fn analyze(args: &Args) -> Result<(), Failure> {
+    let rom = args.positional(0, "a ROM")?;
+    let report = analyzer::analyze(&read(Path::new(rom))?);
+    print!("{}", report);
+
+    if !report.invalid.is_empty() || !report.outside.is_empty() || !report.underflows.is_empty() {
+        return Err(Failure::Error(format!("{} has errors", rom)));
+    }
+    Ok(())
+}
+
+fn test_roms(args: &Args) -> Result<(), Failure> {
+    let frames = args.number("--frames")?.unwrap_or(600);
+    let database = load_database()?;
+    let mut failed = 0;
+
+    for (hash, game) in database.sorted() {
+        let path = match database.path(game) {
+            Some(path) => path,
+            None => continue
+        };
+        let bytes = match read(&path) {
+            Ok(bytes) => bytes,
+            Err(Failure::Error(error)) | Err(Failure::Usage(error)) => {
+                println!("fail  {}: {}", game.title, error);
+                failed += 1;
+                continue;
+            }
+        };
+
+        // the user's config is left out so the results do not depend on it
+        let settings = Settings::from_game(hash, Some(game), &Config::default(), &Overrides::default());
+        let mut chip8 = Chip8::new(&bytes, settings.hack());
+        match headless::run(&mut chip8, settings.ipf, frames, &mut Nothing, &mut Nothing, &mut Filter::new(Flicker::Off)) {
+            Err(fault) => {
+                println!("fail  {}: {}", game.title, fault);
+                failed += 1;
+            },
+            Ok(0) => println!("warn  {}: never drew", game.title),
+            Ok(_) if chip8.cpu.halted => println!("ok    {}: waiting for a key", game.title),
+            Ok(_) => println!("ok    {}", game.title)
+        }
+    }
+
+    if failed > 0 {
+        return Err(Failure::Error(format!("{} games failed", failed)));
+    }
+    Ok(())
+}
+
+fn list(_: &Args) -> Result<(), Failure> {
+    for (i, (_, game)) in load_database()?.sorted().iter().enumerate() {
+        println!("{} {}", i, game.title);
+    }
+    Ok(())
+}
+
+fn search(args: &Args) -> Result<(), Failure> {
+    if args.positional.is_empty() {
+        return Err(Failure::Usage("missing the text to search".to_string()));
+    }
+    let database = load_database()?;
+    let found = library::search(&database, &args.positional.join(" "));
+    if found.is_empty() {
+        return Err(Failure::Error("no game matches".to_string()));
+    }
+    for (i, game) in found {
+        println!("{} {}", i, game.title);
+    }
+    Ok(())
+}
+
+fn info(args: &Args) -> Result<(), Failure> {
+    let database = load_database()?;
+    let rom = library::resolve(&database, args.positional(0, "a game")?)?;
+    print!("{}", library::info(&database, &rom, &read(&rom)?));
+    Ok(())
+}
+
+fn validate(_: &Args) -> Result<(), Failure> {
+    let problems = load_database()?.validate();
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    if !problems.is_empty() {
+        return Err(Failure::Error(format!("{} has {} problems", database::PATH, problems.len())));
+    }
+    println!("{} is valid", database::PATH);
+    Ok(())
+}
+
+fn config(_: &Args) -> Result<(), Failure> {
+    let path = Config::path().ok_or_else(|| "there is no config directory, set CHIP8_CONFIG".to_string())?;
+    Config::load()?;
+    match path.is_file() {
+        true => println!("{} is valid", path.display()),
+        false => println!("{} does not exist, the defaults are used", path.display())
+    }
+    Ok(())
+}
+
+fn load_database() -> Result<Database, Failure> {
+    Ok(Database::load(database::PATH)?)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, Failure> {
+    Ok(fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?)
+}
+
+#[cfg(feature = "piston")]
+fn play() -> Result<(), String> {
+    let database = Database::load(database::PATH)?;
+    let config = Config::load()?;
+    let load = |bytes: &[u8]| {
+        let settings = Settings::resolve(bytes, &config, &Overrides::default());
+        emulator(bytes, &settings, None).map(|chip8| (chip8, settings))
+    };
+
+    let settings = Settings::from_game("", None, &config, &Overrides::default());
+    let mut window = Window::open(run::size(&settings), true)?;
+    let mut menu = Menu::new(&database, &config);
+    // back to the menu after Escape, until the menu is left too or the window closes
+    while let Some(rom) = menu.pick(&mut window)? {
+        let bytes = fs::read(&rom).map_err(|e| format!("{}: {}", rom.display(), e))?;
+        let game = load(&bytes)?;
+        if run::run(&mut window, game, bytes, load)? {
+            break;
+        }
+    }
+    window.save();
+    Ok(())
+}
+
+#[cfg(not(feature = "piston"))]
+fn play() -> Result<(), String> {
+    Err("this build has no window to pick a game in, use chip8 run".to_string())
+}
@@ -0,0 +1,273 @@
+use data::{self, Palette};
+use database::{Game, Quirks};
+use effects::Effect;
+use flicker::Flicker;
+#[cfg(not(feature = "gamepad"))]
+use hardware::Control;
+use orientation::Flip;
+use screenshot::Format;
+use upscale::Upscaler;
+use video;
+use dirs;
+use serde::Deserialize;
+#[cfg(feature = "piston")]
+use serde::Serialize;
+use serde_json;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+#[cfg(feature = "piston")]
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+// Either the name of a profile, like "schip", or the quirks one by one
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum QuirkProfile {
+    Name(String),
+    Quirks(Quirks)
+}
+
+impl QuirkProfile {
+    pub fn quirks(&self) -> Option<Quirks> {
+        match self {
+            QuirkProfile::Name(name) => Quirks::profile(name),
+            QuirkProfile::Quirks(quirks) => Some(quirks.clone())
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct Audio {
+    // rings the terminal bell when the sound timer starts
+    pub enabled: Option<bool>
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct Screenshots {
+    // where F12 saves, screenshots in the working directory by default
+    pub folder: Option<String>,
+    // png, ppm or pbm
+    pub format: Option<Format>
+}
+
+// Every field is optional and falls back to the database and then to the defaults
+#[derive(Deserialize, Clone, Default)]
+pub struct Profile {
+    pub scale: Option<u32>,
+    pub palette: Option<String>,
+    // instructions per frame
+    pub ipf: Option<u32>,
+    // qwerty, azerty, qwertz, dvorak or keypad
+    pub layout: Option<String>,
+    // from a key or gamepad name to a hex key, like the database, several can share a hex key
+    pub keys: Option<BTreeMap<String, String>>,
+    pub quirks: Option<QuirkProfile>,
+    pub audio: Option<Audio>,
+    // writes an instruction trace to this file
+    pub trace: Option<String>,
+    // shows the clickable keypad next to the screen
+    pub keypad: Option<bool>,
+    // "off", { "blend": 3 }, { "decay": 120 } or { "stable": 2 }
+    pub flicker: Option<Flicker>,
+    // lines of background between pixels and whether they show as a grid
+    pub gap: Option<u32>,
+    pub grid: Option<bool>,
+    // nearest, scale2x, scale3x, hqx or lcd
+    pub upscaler: Option<Upscaler>,
+    // any of scanlines, mask, bloom and led
+    pub effects: Option<Vec<Effect>>,
+    // lets the window scale by any factor, not only whole ones
+    pub fractional: Option<bool>,
+    // clockwise, 0, 90, 180 or 270
+    pub rotation: Option<u16>,
+    pub flip: Option<Flip>,
+    // how many times faster fast forward is, 0 for as fast as it can
+    pub turbo: Option<u32>,
+    // shows the frames and instructions per second, the title and the sound under the screen
+    pub status: Option<bool>,
+    pub screenshots: Option<Screenshots>
+}
+
+// The settings a game has in the database, to layer it like the profiles
+impl From<&Game> for Profile {
+    fn from(game: &Game) -> Profile {
+        Profile {
+            palette: game.palette.clone(),
+            ipf: game.ipf,
+            keys: game.keys.clone(),
+            quirks: game.quirks.clone().map(QuirkProfile::Quirks),
+            rotation: game.rotation,
+            flip: game.flip,
+            ..Profile::default()
+        }
+    }
+}
+
+// The defaults plus a profile per game, keyed by the SHA-1 of the ROM or its title:
+// { "scale": 8, "games": { "pong": { "ipf": 12 } } }
+#[derive(Deserialize, Default)]
+pub struct Config {
+    #[serde(flatten)]
+    pub defaults: Profile,
+    #[serde(default)]
+    pub games: BTreeMap<String, Profile>,
+    // the user's own palettes, 2 or 4 colors like "#102030"
+    #[serde(default)]
+    pub palettes: BTreeMap<String, Vec<String>>
+}
+
+impl Config {
+    // CHIP8_CONFIG or chip8/config.json in the user's config directory,
+    // like ~/.config/chip8/config.json on Linux
+    pub fn path() -> Option<PathBuf> {
+        match env::var_os("CHIP8_CONFIG") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => dirs::config_dir().map(|dir| dir.join("chip8").join("config.json"))
+        }
+    }
+
+    // A missing file is an empty config
+    pub fn load() -> Result<Config, String> {
+        let path = match Config::path() {
+            Some(path) => path,
+            None => return Ok(Config::default())
+        };
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(format!("{}: {}", path.display(), e))
+        };
+
+        let config: Config = serde_json::from_reader(file).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let problems = config.validate();
+        if !problems.is_empty() {
+            return Err(format!("{}: {}", path.display(), problems.join(", ")));
+        }
+        // kept for builds that have gamepads, but said, since they do nothing here
+        #[cfg(not(feature = "gamepad"))]
+        for key in config.gamepad_keys() {
+            eprintln!("{}: {} is a gamepad control, this build has no gamepads, build with --features gamepad", path.display(), key);
+        }
+        Ok(config)
+    }
+
+    pub fn game(&self, hash: &str, title: Option<&str>) -> Option<&Profile> {
+        self.games.get(hash).or_else(|| {
+            let title = title?;
+            self.games.iter().find(|(name, _)| name.eq_ignore_ascii_case(title)).map(|(_, profile)| profile)
+        })
+    }
+
+    // The built in palettes in their order and then the user's, a user palette replaces a built in one of the same name
+    pub fn palettes(&self) -> Vec<(String, Palette)> {
+        let mine = |name: &str| self.palettes.get(name).and_then(|colors| data::parse_palette(colors).ok());
+        let mut palettes: Vec<(String, Palette)> = data::PALETTES.iter()
+            .filter_map(|name| mine(name).or_else(|| data::palette(name)).map(|palette| (name.to_string(), palette)))
+            .collect();
+        for name in self.palettes.keys().filter(|name| !data::PALETTES.contains(&name.as_str())) {
+            if let Some(palette) = mine(name) {
+                palettes.push((name.clone(), palette));
+            }
+        }
+        palettes
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    fn gamepad_keys(&self) -> Vec<&str> {
+        let profiles = Some(&self.defaults).into_iter().chain(self.games.values());
+        profiles.flat_map(|profile| profile.keys.iter().flatten().map(|(key, _)| key.as_str()))
+            .filter(|key| data::controls_from_name(key).iter().any(|control| !matches!(control, Control::Key(_))))
+            .collect()
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        for (name, colors) in self.palettes.iter() {
+            if let Err(error) = data::parse_palette(colors) {
+                problems.push(format!("palette {}: {}", name, error));
+            }
+        }
+        let profiles = Some(("defaults", &self.defaults)).into_iter()
+            .chain(self.games.iter().map(|(name, profile)| (name.as_str(), profile)));
+
+        for (name, profile) in profiles {
+            let mut problem = |text: String| problems.push(format!("{}: {}", name, text));
+
+            if let Some(QuirkProfile::Name(ref quirks)) = profile.quirks {
+                if Quirks::profile(quirks).is_none() {
+                    problem(format!("unknown quirk profile {}, use chip8, schip or none", quirks));
+                }
+            }
+            if let Some(ref palette) = profile.palette {
+                if data::palette(palette).is_none() && !self.palettes.contains_key(palette) {
+                    problem(format!("unknown palette {}", palette));
+                }
+            }
+            if let Some(ref layout) = profile.layout {
+                if data::layout(layout).is_none() {
+                    problem(format!("unknown layout {}, use {}", layout, data::LAYOUTS.join(", ")));
+                }
+            }
+            for (key, value) in profile.keys.iter().flatten() {
+                if data::controls_from_name(key).is_empty() {
+                    problem(format!("unknown key {}", key));
+                }
+                if u8::from_str_radix(value, 16).ok().filter(|&x| x < 16).is_none() {
+                    problem(format!("{} is bound to {}, not a hex key", key, value));
+                }
+            }
+            match profile.flicker {
+                Some(Flicker::Blend(0)) | Some(Flicker::Decay(0)) | Some(Flicker::Stable(0)) => {
+                    problem("the number of a flicker mode must be at least 1".to_string());
+                },
+                _ => ()
+            }
+            match profile.rotation {
+                None | Some(0) | Some(90) | Some(180) | Some(270) => (),
+                Some(rotation) => problem(format!("rotation {} is not 0, 90, 180 or 270", rotation))
+            }
+            if profile.ipf == Some(0) || profile.scale == Some(0) {
+                problem("ipf and scale must be at least 1".to_string());
+            }
+            if let Some(scale) = profile.scale.filter(|&scale| scale > video::MAX_SCALE) {
+                problem(format!("scale {} is too big, the most is {}", scale, video::MAX_SCALE));
+            }
+        }
+
+        problems
+    }
+}
+
+// Where the window was and how big, kept between runs next to the config
+#[cfg(feature = "piston")]
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct Geometry {
+    pub position: [i32; 2],
+    pub size: [u32; 2],
+    pub fullscreen: bool
+}
+
+#[cfg(feature = "piston")]
+impl Geometry {
+    fn path() -> Option<PathBuf> {
+        Config::path().map(|path| path.with_file_name("window.json"))
+    }
+
+    // Anything wrong with the file just means the window starts where it would the first time
+    pub fn load() -> Option<Geometry> {
+        let file = File::open(Geometry::path()?).ok()?;
+        serde_json::from_reader(file).ok()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Geometry::path().ok_or("there is no config directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(&path, json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
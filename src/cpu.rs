@@ -1,7 +1,11 @@
 use rand;
+use std::fmt;
+
+// Addresses past the end of memory wrap around to its start
+pub const MEMORY: usize = 4096;
 
 pub struct Cpu {
-    pub memory: [u8; 4096],
+    pub memory: [u8; MEMORY],
     pub v: [u8; 16],
     pub i: u16,
     pub pc: u16,    
@@ -9,9 +13,26 @@ pub struct Cpu {
     pub sound_timer: u8,
     pub stack: Vec<u16>,
     pub halted: bool,
+    // set when the program did something the CPU can't go on from, it stops until a reset
+    pub fault: Option<Fault>,
     pub hack: Hack
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Fault {
+    // 00EE at this address with nothing on the stack
+    StackUnderflow(u16)
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::StackUnderflow(pc) => write!(f, "return with an empty stack at {:03X}", pc)
+        }
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
 pub enum Message {
     Clear,
     Draw(u8, u8, u8),
@@ -20,15 +41,29 @@ pub enum Message {
     NoMessage
 }
 
+#[derive(Clone, Copy)]
 pub struct Hack {
     pub shift_hack: bool,
     pub memory_hack: bool
 }
 
+// Whether the two bytes are an instruction the CPU runs, anything else is skipped doing nothing
+pub fn known(byte1: u8, byte2: u8) -> bool {
+    let n = byte2 & 15;
+    match byte1 >> 4 {
+        0x0 => byte1 == 0 && (byte2 == 0xE0 || byte2 == 0xEE),
+        0x5 | 0x9 => n == 0,
+        0x8 => n <= 0x7 || n == 0xE,
+        0xE => byte2 == 0x9E || byte2 == 0xA1,
+        0xF => [0x07, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65].contains(&byte2),
+        _ => true
+    }
+}
+
 impl Cpu {
     pub fn new(hack: Hack) -> Cpu {
         Cpu {
-            memory: [0; 4096],
+            memory: [0; MEMORY],
             v: [0; 16],
             i: 0,
             pc: 512,
@@ -36,14 +71,19 @@ impl Cpu {
             sound_timer: 0,
             stack: vec![],
             halted: false,
+            fault: None,
             hack
         }
     }
 
+    // The two bytes at the program counter
+    pub fn opcode(&self) -> (u8, u8) {
+        (self.memory[self.pc as usize % MEMORY], self.memory[(self.pc as usize + 1) % MEMORY])
+    }
+
     pub fn step(&mut self) -> Message {       
-        let byte1 = self.memory[self.pc as usize];
-        let byte2 = self.memory[self.pc as usize + 1];
-        self.pc += 2;
+        let (byte1, byte2) = self.opcode();
+        self.pc = (self.pc + 2) % MEMORY as u16;
 
         let bits1 = byte1 >> 4 & 15;
         let bits2 = byte1 & 15 ;
@@ -65,11 +105,7 @@ impl Cpu {
             0xA => self.memory(bits2, bits3, bits4),
             0xB => self.jump_plus(bits2, bits3, bits4),
             0xC => self.random(bits2, bits3, bits4),
-            0xF => {
-                if byte2 != 0x0A {
-                    self.others(bits2, bits3, bits4)
-                }
-            },
+            0xF if byte2 != 0x0A => self.others(bits2, bits3, bits4),
             _ => ()
         }
 
@@ -108,7 +144,13 @@ impl Cpu {
             0x0E0 => Message::Clear,
             // 00EE 	Flow 	return; 	Returns from a subroutine.
             0x0EE => {
-                self.pc = self.stack.pop().unwrap();
+                match self.stack.pop() {
+                    Some(pc) => self.pc = pc,
+                    None => {
+                        self.pc = (self.pc + MEMORY as u16 - 2) % MEMORY as u16;
+                        self.fault = Some(Fault::StackUnderflow(self.pc));
+                    }
+                }
                 Message::NoMessage
             },
             _ => Message::NoMessage
@@ -283,7 +325,8 @@ impl Cpu {
             // FX1E 	MEM 	I +=Vx 	Adds VX to I.[3]
             0x1E => {
                 self.i += v[x] as u16;
-                v[15] = if self.i >= 4096 { 1 } else { 0 };
+                v[15] = if self.i as usize >= MEMORY { 1 } else { 0 };
+                self.i %= MEMORY as u16;
             }
 
             // FX29 	MEM 	I=sprite_addr[Vx]
@@ -300,31 +343,29 @@ impl Cpu {
             // the tens digit at location I+1,
             // and the ones digit at location I+2
             0x33 => {
-                self.memory[self.i as usize]     = v[x] / 100;
-                self.memory[self.i as usize + 1] = v[x] % 100 / 10;
-                self.memory[self.i as usize + 2] = v[x] % 10;
+                self.memory[self.i as usize % MEMORY]       = v[x] / 100;
+                self.memory[(self.i as usize + 1) % MEMORY] = v[x] % 100 / 10;
+                self.memory[(self.i as usize + 2) % MEMORY] = v[x] % 10;
             },
 
             // FX55 	MEM 	reg_dump(Vx,&I)
             // Stores V0 to VX (including VX) in memory starting at address I. I is increased by 1 for each value written.
             0x55 => {
-                for j in 0..bits2 + 1 {
-                    self.memory[self.i as usize] = v[j as usize];
-                    self.i += 1;
+                for (j, &value) in v[..=x].iter().enumerate() {
+                    self.memory[(self.i as usize + j) % MEMORY] = value;
                 }
-                if self.hack.memory_hack {
-                    self.i -= bits2 as u16 + 1;
+                if !self.hack.memory_hack {
+                    self.i = (self.i + x as u16 + 1) % MEMORY as u16;
                 }
             },
             0x65 => {
                 // FX65 	MEM 	reg_load(Vx,&I)
                 // Fills V0 to VX (including VX) with values from memory starting at address I. I is increased by 1 for each value written.
-                for j in 0..bits2 + 1 {
-                    v[j as usize] = self.memory[self.i as usize];
-                    self.i += 1;
+                for (j, value) in v[..=x].iter_mut().enumerate() {
+                    *value = self.memory[(self.i as usize + j) % MEMORY];
                 }
-                if self.hack.memory_hack {
-                    self.i -= bits2 as u16 + 1;
+                if !self.hack.memory_hack {
+                    self.i = (self.i + x as u16 + 1) % MEMORY as u16;
                 }
             }
             _ => ()
@@ -1,10 +1,72 @@
-use piston_window::Key;
+use hardware::{Control, Direction};
+use key;
 
 pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
 pub const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
 pub const BLUE: [f32; 4] = [0.16, 0.21, 0.58, 1.0];
 pub const GRAY: [f32; 4] = [0.93, 0.94, 0.95, 1.0];
 
+// Indexed by the value of a pixel: background, the first plane, the second plane and both planes.
+// Screens with one plane only use the first two colors
+pub type Palette = [[f32; 4]; 4];
+
+pub const PALETTES: [&str; 8] = ["blue", "classic", "green", "amber", "lcd", "high-contrast", "colorblind", "octo"];
+
+pub fn palette(name: &str) -> Option<Palette> {
+    let colors = match name {
+        "blue" => return Some([BLUE, GRAY, [0.55, 0.58, 0.77, 1.0], WHITE]),
+        "classic" => return Some([BLACK, WHITE, rgb(0xAAAAAA), rgb(0x555555)]),
+        "green" => [0x0A140A, 0x33FF66, 0x1A8033, 0xA6FFBF],
+        "amber" => [0x140C00, 0xFFB000, 0x805800, 0xFFD98C],
+        // the greens of an old handheld LCD
+        "lcd" => [0x9BBC0F, 0x0F380F, 0x8BAC0F, 0x306230],
+        "high-contrast" => [0x000000, 0xFFFF00, 0x00FFFF, 0xFFFFFF],
+        // orange, sky blue and yellow of the Okabe-Ito set tell apart with any color blindness
+        "colorblind" => [0x000000, 0xE69F00, 0x56B4E9, 0xF0E442],
+        "octo" => [0x996600, 0xFFCC00, 0xFF6600, 0x662200],
+        _ => return None
+    };
+    Some([rgb(colors[0]), rgb(colors[1]), rgb(colors[2]), rgb(colors[3])])
+}
+
+// Palettes are in sRGB like the images, windows draw colors as linear
+#[cfg(feature = "piston")]
+pub fn linear(color: [f32; 4]) -> [f32; 4] {
+    let channel = |c: f32| if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+    [channel(color[0]), channel(color[1]), channel(color[2]), color[3]]
+}
+
+pub fn rgb(hex: u32) -> [f32; 4] {
+    let channel = |shift: u32| (hex >> shift & 0xFF) as f32 / 255.0;
+    [channel(16), channel(8), channel(0), 1.0]
+}
+
+// Two or four colors written as #RRGGBB, with two the other planes blend them
+pub fn parse_palette(colors: &[String]) -> Result<Palette, String> {
+    let mut parsed = vec![];
+    for color in colors {
+        match color.strip_prefix('#').filter(|x| x.len() == 6).and_then(|x| u32::from_str_radix(x, 16).ok()) {
+            Some(hex) => parsed.push(rgb(hex)),
+            None => return Err(format!("{} is not a #RRGGBB color", color))
+        }
+    }
+
+    match *parsed.as_slice() {
+        [background, foreground] => {
+            let mix = |a: f32, b: f32| (a + b) / 2.0;
+            let middle = [
+                mix(background[0], foreground[0]),
+                mix(background[1], foreground[1]),
+                mix(background[2], foreground[2]),
+                1.0
+            ];
+            Ok([background, foreground, middle, foreground])
+        },
+        [background, first, second, both] => Ok([background, first, second, both]),
+        _ => Err("a palette has 2 or 4 colors".to_string())
+    }
+}
+
 pub fn fontset() -> [u8; 80] {
     [
         0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -26,24 +88,77 @@ pub fn fontset() -> [u8; 80] {
     ]
 }
 
-pub fn key_to_number(key: Key) -> usize {
-    match key {
-        Key::D1 => 0x1,
-        Key::D2 => 0x2,
-        Key::D3 => 0x3,
-        Key::D4 => 0xC,
-        Key::Q => 0x4,
-        Key::W => 0x5,
-        Key::E => 0x6,
-        Key::R => 0xD,
-        Key::A => 0x7,
-        Key::S => 0x8,
-        Key::D => 0x9,
-        Key::F => 0xE,
-        Key::Z => 0xA,
-        Key::X => 0x0,
-        Key::C => 0xB,
-        Key::V => 0xF,
-        _ => 0x10
+// The 16 keys in the order of the COSMAC VIP pad, row by row:
+// 1 2 3 C
+// 4 5 6 D
+// 7 8 9 E
+// A 0 B F
+pub const PAD: [usize; 16] = [0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF];
+
+pub const LAYOUTS: [&str; 5] = ["qwerty", "azerty", "qwertz", "dvorak", "keypad"];
+
+// Bindings for a keyboard layout, the keyboard layouts use the 4x4 block from 1 to V on QWERTY
+pub fn layout(name: &str) -> Option<Vec<(Control, usize)>> {
+    use key::Key::*;
+
+    let keys = match name {
+        "qwerty" => [D1, D2, D3, D4, Q, W, E, R, A, S, D, F, Z, X, C, V],
+        "azerty" => [D1, D2, D3, D4, A, Z, E, R, Q, S, D, F, W, X, C, V],
+        "qwertz" => [D1, D2, D3, D4, Q, W, E, R, A, S, D, F, Y, X, C, V],
+        "dvorak" => [D1, D2, D3, D4, Quote, Comma, Period, P, A, O, E, U, Semicolon, Q, J, K],
+        // the number pad by label, with A to F on / * - + Enter .
+        "keypad" => [
+            NumPad1, NumPad2, NumPad3, NumPadMinus, NumPad4, NumPad5, NumPad6, NumPadPlus,
+            NumPad7, NumPad8, NumPad9, NumPadEnter, NumPadDivide, NumPad0, NumPadMultiply, NumPadPeriod
+        ],
+        _ => return None
+    };
+    Some(keys.iter().map(|&key| Control::Key(key)).zip(PAD.iter().cloned()).collect())
+}
+
+// The D-pad and the left stick move on 2, 4, 6 and 8, A is 5, like most games expect
+#[cfg(feature = "gamepad")]
+pub const GAMEPAD: [(&str, usize); 9] = [
+    ("DPadUp", 0x2), ("DPadDown", 0x8), ("DPadLeft", 0x4), ("DPadRight", 0x6),
+    ("LeftStickUp", 0x2), ("LeftStickDown", 0x8), ("LeftStickLeft", 0x4), ("LeftStickRight", 0x6),
+    ("PadA", 0x5)
+];
+
+// A key name or a gamepad name, empty if unknown. Gamepad names follow the numbering of
+// SDL game controllers, other pads can be bound with Button<n>, Axis<n>+ and Axis<n>-
+pub fn controls_from_name(name: &str) -> Vec<Control> {
+    use hardware::Control::*;
+
+    match name {
+        "DPadUp" => vec![Hat(Direction::Up), Button(11)],
+        "DPadDown" => vec![Hat(Direction::Down), Button(12)],
+        "DPadLeft" => vec![Hat(Direction::Left), Button(13)],
+        "DPadRight" => vec![Hat(Direction::Right), Button(14)],
+        "LeftStickUp" => vec![Axis(1, false)],
+        "LeftStickDown" => vec![Axis(1, true)],
+        "LeftStickLeft" => vec![Axis(0, false)],
+        "LeftStickRight" => vec![Axis(0, true)],
+        "RightStickUp" => vec![Axis(3, false)],
+        "RightStickDown" => vec![Axis(3, true)],
+        "RightStickLeft" => vec![Axis(2, false)],
+        "RightStickRight" => vec![Axis(2, true)],
+        "PadA" => vec![Button(0)],
+        "PadB" => vec![Button(1)],
+        "PadX" => vec![Button(2)],
+        "PadY" => vec![Button(3)],
+        "PadBack" => vec![Button(4)],
+        "PadStart" => vec![Button(6)],
+        "PadLeftShoulder" => vec![Button(9)],
+        "PadRightShoulder" => vec![Button(10)],
+        _ => {
+            if let Some(number) = name.strip_prefix("Button").and_then(|x| x.parse().ok()) {
+                vec![Button(number)]
+            } else if let Some(axis) = name.strip_prefix("Axis").filter(|x| x.ends_with('+') || x.ends_with('-')) {
+                let (number, sign) = axis.split_at(axis.len() - 1);
+                number.parse().map(|number| vec![Axis(number, sign == "+")]).unwrap_or_default()
+            } else {
+                key::Key::from_name(name).map(Key).into_iter().collect()
+            }
+        }
     }
 }
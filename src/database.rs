@@ -0,0 +1,186 @@
+use analyzer::Platform;
+use data;
+use orientation::Flip;
+use serde::{Deserialize, Serialize};
+use serde_json;
+use sha1::Sha1;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub const PATH: &str = "roms/roms.json";
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Quirks {
+    pub load_store: Option<bool>,
+    pub shift: Option<bool>
+}
+
+impl Quirks {
+    // chip8 is how the COSMAC VIP behaves, schip shifts VX in place and leaves I alone on FX55/FX65
+    pub fn profile(name: &str) -> Option<Quirks> {
+        match name {
+            "chip8" | "none" => Some(Quirks { load_store: Some(false), shift: Some(false) }),
+            "schip" => Some(Quirks { load_store: Some(true), shift: Some(true) }),
+            _ => None
+        }
+    }
+}
+
+// Everything but the title and description is optional and falls back to the defaults
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Game {
+    pub title: String,
+    pub file: Option<String>,
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    // instructions per frame
+    pub ipf: Option<u32>,
+    // extra bindings, from a key name or a gamepad name to a hex key
+    pub keys: Option<BTreeMap<String, String>>,
+    pub palette: Option<String>,
+    pub rotation: Option<u16>,
+    pub flip: Option<Flip>,
+    pub description: String
+}
+
+// Games keyed by the SHA-1 of the ROM, files are relative to the database
+pub struct Database {
+    pub dir: PathBuf,
+    pub games: BTreeMap<String, Game>
+}
+
+impl Database {
+    pub fn load(path: &str) -> Result<Database, String> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let games = serde_json::from_reader(file).map_err(|e| format!("{}: {}", path, e))?;
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        Ok(Database { dir, games })
+    }
+
+    pub fn find(&self, rom: &[u8]) -> Option<&Game> {
+        self.games.get(&hash(rom))
+    }
+
+    pub fn path(&self, game: &Game) -> Option<PathBuf> {
+        game.file.as_ref().map(|file| self.dir.join(file))
+    }
+
+    // Sorted by title, which is the order the games are listed in
+    pub fn sorted(&self) -> Vec<(&String, &Game)> {
+        let mut games: Vec<(&String, &Game)> = self.games.iter().collect();
+        games.sort_by(|a, b| a.1.title.cmp(&b.1.title));
+        games
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        for (hash, game) in self.sorted() {
+            let mut problem = |text: String| problems.push(format!("{} ({}): {}", game.title, hash, text));
+
+            match self.path(game) {
+                Some(path) => {
+                    let mut rom = vec![];
+                    match File::open(&path).and_then(|mut file| file.read_to_end(&mut rom)) {
+                        Ok(_) => {
+                            let actual = self::hash(&rom);
+                            if &actual != hash {
+                                problem(format!("{} hashes to {}", path.display(), actual));
+                            }
+                        },
+                        Err(e) => problem(format!("{}: {}", path.display(), e))
+                    }
+                },
+                // it can't be played from the library without one
+                None => problem("no file".to_string())
+            }
+
+            if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()) {
+                problem("the key is not a lowercase SHA-1".to_string());
+            }
+
+            for (key, value) in game.keys.iter().flatten() {
+                if data::controls_from_name(key).is_empty() {
+                    problem(format!("unknown key {}", key));
+                }
+                if u8::from_str_radix(value, 16).ok().filter(|&x| x < 16).is_none() {
+                    problem(format!("{} is bound to {}, not a hex key", key, value));
+                }
+            }
+
+            if let Some(0) = game.ipf {
+                problem("ipf must be at least 1".to_string());
+            }
+
+            if let Some(ref palette) = game.palette {
+                if data::palette(palette).is_none() {
+                    problem(format!("unknown palette {}", palette));
+                }
+            }
+
+            match game.rotation {
+                None | Some(0) | Some(90) | Some(180) | Some(270) => (),
+                Some(rotation) => problem(format!("rotation {} is not 0, 90, 180 or 270", rotation))
+            }
+        }
+
+        problems
+    }
+}
+
+pub fn hash(rom: &[u8]) -> String {
+    Sha1::from(rom).digest().to_string()
+}
+
+// Settings for a ROM loaded from anywhere, None if the database is missing or does not know it
+pub fn lookup(rom: &[u8]) -> Option<Game> {
+    Database::load(PATH).ok().and_then(|database| database.find(rom).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash, Database, Game};
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::fs;
+
+    fn game(title: &str, file: Option<&str>) -> Game {
+        Game {
+            title: title.to_string(),
+            file: file.map(|file| file.to_string()),
+            platform: None,
+            quirks: None,
+            ipf: None,
+            keys: None,
+            palette: None,
+            rotation: None,
+            flip: None,
+            description: String::new()
+        }
+    }
+
+    #[test]
+    fn validate_finds_roms_that_are_missing_changed_or_not_named() {
+        let dir = env::temp_dir().join(format!("chip8-database-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("good.ch8"), [0x12, 0x00]).unwrap();
+        fs::write(dir.join("changed.ch8"), [0x12, 0x02]).unwrap();
+
+        let mut games = BTreeMap::new();
+        games.insert(hash(&[0x12, 0x00]), game("Good", Some("good.ch8")));
+        games.insert(hash(&[0x00, 0xE0]), game("Changed", Some("changed.ch8")));
+        games.insert(hash(&[0x00, 0xEE]), game("Missing", Some("missing.ch8")));
+        games.insert(hash(&[0xE0, 0x00]), game("Nameless", None));
+        let problems = Database { dir: dir.clone(), games }.validate();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].starts_with(&format!("Changed ({}): ", hash(&[0x00, 0xE0]))), "{}", problems[0]);
+        assert!(problems[0].ends_with(&format!("changed.ch8 hashes to {}", hash(&[0x12, 0x02]))), "{}", problems[0]);
+        assert!(problems[1].starts_with("Missing (") && problems[1].contains("missing.ch8: "), "{}", problems[1]);
+        assert!(problems[2].starts_with("Nameless (") && problems[2].ends_with(": no file"), "{}", problems[2]);
+    }
+}
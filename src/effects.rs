@@ -0,0 +1,198 @@
+use data::Palette;
+use serde::Deserialize;
+use video::{self, Frame, Options};
+
+// Looks of old screens, drawn over the scaled frame
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    // darker lines between the rows of pixels
+    Scanlines,
+    // red, green and blue stripes like the aperture grille of a CRT
+    Mask,
+    // bright pixels glow over their surroundings
+    Bloom,
+    // round pixels like a matrix of LEDs
+    Led
+}
+
+pub const NAMES: [&str; 4] = ["scanlines", "mask", "bloom", "led"];
+
+// What F4 turns on when a game has no effects of its own
+#[cfg(feature = "piston")]
+pub const CRT: [Effect; 3] = [Effect::Scanlines, Effect::Mask, Effect::Bloom];
+
+impl Effect {
+    pub fn parse(name: &str) -> Option<Effect> {
+        match name {
+            "scanlines" => Some(Effect::Scanlines),
+            "mask" => Some(Effect::Mask),
+            "bloom" => Some(Effect::Bloom),
+            "led" => Some(Effect::Led),
+            _ => None
+        }
+    }
+}
+
+// The effects always go in the same order, whatever order they are listed in
+pub fn apply(frame: &mut Frame, palette: Palette, options: &Options) {
+    gaps(frame, palette, options);
+    if options.effects.contains(&Effect::Led) {
+        led(frame, palette, options);
+    }
+    if options.effects.contains(&Effect::Scanlines) {
+        scanlines(frame, options.scale.max(1));
+    }
+    if options.effects.contains(&Effect::Mask) {
+        mask(frame);
+    }
+    if options.effects.contains(&Effect::Bloom) {
+        bloom(frame, options.scale.max(1));
+    }
+}
+
+// The last gap rows and columns of every scale x scale block go back to the background
+fn gaps(frame: &mut Frame, palette: Palette, options: &Options) {
+    let scale = options.scale.max(1);
+    let gap = options.gap.min(scale - 1);
+    if gap == 0 {
+        return;
+    }
+    let color = video::bytes(if options.grid { video::mix(palette[0], palette[1], 0.2) } else { palette[0] });
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            if x % scale >= scale - gap || y % scale >= scale - gap {
+                frame.set(x, y, color);
+            }
+        }
+    }
+}
+
+// Every block keeps a disc in the middle of the part left by the gap, with a soft border
+fn led(frame: &mut Frame, palette: Palette, options: &Options) {
+    let scale = options.scale.max(1);
+    let size = (scale - options.gap.min(scale - 1)) as f32;
+    let radius = size / 2.0;
+    let background = video::bytes(palette[0]);
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let dx = (x % scale) as f32 + 0.5 - radius;
+            let dy = (y % scale) as f32 + 0.5 - radius;
+            let coverage = (radius - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+            if coverage < 1.0 {
+                let color = frame.get(x, y);
+                frame.set(x, y, blend(background, color, coverage));
+            }
+        }
+    }
+}
+
+// The rows of every pixel get darker towards its top and bottom edges
+fn scanlines(frame: &mut Frame, scale: u32) {
+    if scale < 2 {
+        return;
+    }
+    for y in 0..frame.height {
+        let position = ((y % scale) as f32 + 0.5) / scale as f32;
+        let brightness = 0.55 + 0.45 * (position * std::f32::consts::PI).sin();
+        for x in 0..frame.width {
+            let color = frame.get(x, y);
+            frame.set(x, y, tint(color, [brightness; 3]));
+        }
+    }
+}
+
+// Columns take turns at letting red, green and blue through
+fn mask(frame: &mut Frame) {
+    // a bit over 1 so the screen is not dimmer with the stripes
+    let stripes = [[1.2, 0.85, 0.85], [0.85, 1.2, 0.85], [0.85, 0.85, 1.2]];
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let color = frame.get(x, y);
+            frame.set(x, y, tint(color, stripes[x as usize % 3]));
+        }
+    }
+}
+
+// A blurred copy of the frame is added over it
+fn bloom(frame: &mut Frame, scale: u32) {
+    let radius = (scale / 2).max(1) as usize;
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let mut light: Vec<[f32; 3]> = frame.pixels.chunks(4).map(|p| [p[0] as f32, p[1] as f32, p[2] as f32]).collect();
+    // a box blur, across and then down
+    light = blur(&light, width, height, radius, 1, width);
+    light = blur(&light, height, width, radius, width, 1);
+    for (i, glow) in light.iter().enumerate() {
+        for (channel, value) in glow.iter().enumerate() {
+            let pixel = &mut frame.pixels[i * 4 + channel];
+            *pixel = (*pixel as f32 + value * 0.25).min(255.0) as u8;
+        }
+    }
+}
+
+// Averages every line of `length` pixels `step` apart, `lines` of them `stride` apart
+fn blur(light: &[[f32; 3]], length: usize, lines: usize, radius: usize, step: usize, stride: usize) -> Vec<[f32; 3]> {
+    let mut blurred = vec![[0.0; 3]; light.len()];
+    for line in 0..lines {
+        let at = |n: usize| light[line * stride + n * step];
+        for n in 0..length {
+            let (from, to) = (n.saturating_sub(radius), (n + radius).min(length - 1));
+            let mut sum = [0.0; 3];
+            for m in from..=to {
+                for (total, value) in sum.iter_mut().zip(at(m).iter()) {
+                    *total += value;
+                }
+            }
+            // near the edges the box holds fewer pixels
+            let count = (to - from + 1) as f32;
+            blurred[line * stride + n * step] = [sum[0] / count, sum[1] / count, sum[2] / count];
+        }
+    }
+    blurred
+}
+
+fn tint(color: [u8; 4], factors: [f32; 3]) -> [u8; 4] {
+    let channel = |i: usize| (color[i] as f32 * factors[i]).min(255.0) as u8;
+    [channel(0), channel(1), channel(2), color[3]]
+}
+
+fn blend(a: [u8; 4], b: [u8; 4], amount: f32) -> [u8; 4] {
+    let channel = |i: usize| (a[i] as f32 + (b[i] as f32 - a[i] as f32) * amount).round() as u8;
+    [channel(0), channel(1), channel(2), channel(3)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bloom;
+    use video::Frame;
+
+    // The red of every pixel, row by row
+    fn reds(frame: &Frame) -> Vec<u8> {
+        frame.pixels.chunks(4).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn bloom_spreads_a_lit_pixel_over_its_neighbours() {
+        let mut frame = Frame::new(5, 5);
+        frame.set(2, 2, [200, 200, 200, 255]);
+        bloom(&mut frame, 2);
+        // the box of 9 pixels averages to 22.2, a quarter of it is added
+        assert_eq!(reds(&frame), vec![
+            0, 0, 0, 0, 0,
+            0, 5, 5, 5, 0,
+            0, 5, 205, 5, 0,
+            0, 5, 5, 5, 0,
+            0, 0, 0, 0, 0
+        ]);
+        assert_eq!(frame.get(0, 0)[3], 0);
+    }
+
+    #[test]
+    fn bloom_averages_only_the_pixels_inside_the_frame() {
+        let mut frame = Frame::new(3, 3);
+        frame.set(0, 0, [200, 200, 200, 255]);
+        bloom(&mut frame, 2);
+        // the corner box holds 4 pixels, so the corner gains 12.5
+        assert_eq!(reds(&frame), vec![212, 8, 0, 8, 5, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,141 @@
+use hardware::{Gfx, HEIGHT, WIDTH};
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+// Games erase and redraw sprites with XOR, these hide the frames where a sprite is gone
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Flicker {
+    Off,
+    // the average of the last n frames
+    Blend(u32),
+    // pixels fade out over this many milliseconds, like phosphor
+    Decay(u32),
+    // a pixel changes only after keeping its new value for n frames
+    Stable(u32)
+}
+
+impl Flicker {
+    // off, blend:3, decay:120 or stable:2
+    pub fn parse(text: &str) -> Option<Flicker> {
+        let mut parts = text.splitn(2, ':');
+        let name = parts.next()?;
+        let number = match parts.next() {
+            Some(number) => Some(number.parse::<u32>().ok().filter(|&x| x > 0)?),
+            None => None
+        };
+
+        match name {
+            "off" if number.is_none() => Some(Flicker::Off),
+            "blend" => Some(Flicker::Blend(number.unwrap_or(3))),
+            "decay" => Some(Flicker::Decay(number.unwrap_or(120))),
+            "stable" => Some(Flicker::Stable(number.unwrap_or(2))),
+            _ => None
+        }
+    }
+}
+
+// How bright every pixel is, from 0 to 1
+pub type Levels = [[f32; WIDTH]; HEIGHT];
+
+pub struct Filter {
+    mode: Flicker,
+    history: VecDeque<Gfx>,
+    // how many frames each pixel has had its current value
+    ages: [[u32; WIDTH]; HEIGHT],
+    // the last value other than 0 of every pixel, which picks its color
+    pub colors: Gfx,
+    pub levels: Levels
+}
+
+impl Filter {
+    pub fn new(mode: Flicker) -> Filter {
+        Filter {
+            mode,
+            history: VecDeque::new(),
+            ages: [[0; WIDTH]; HEIGHT],
+            colors: [[0; WIDTH]; HEIGHT],
+            levels: [[0.0; WIDTH]; HEIGHT]
+        }
+    }
+
+    // Takes the screen at the end of every 60 Hz frame
+    pub fn push(&mut self, gfx: &Gfx) {
+        let previous = self.history.back().cloned().unwrap_or(*gfx);
+        self.history.push_back(*gfx);
+        let frames = match self.mode {
+            Flicker::Blend(frames) => frames as usize,
+            _ => 1
+        };
+        while self.history.len() > frames {
+            self.history.pop_front();
+        }
+
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let pixel = gfx[y][x];
+                self.ages[y][x] = if pixel == previous[y][x] { self.ages[y][x].saturating_add(1) } else { 1 };
+                if pixel != 0 {
+                    self.colors[y][x] = pixel;
+                }
+
+                self.levels[y][x] = match self.mode {
+                    Flicker::Off => if pixel != 0 { 1.0 } else { 0.0 },
+                    Flicker::Blend(_) => {
+                        let on = self.history.iter().filter(|frame| frame[y][x] != 0).count();
+                        on as f32 / self.history.len() as f32
+                    },
+                    Flicker::Decay(milliseconds) => {
+                        if pixel != 0 { 1.0 } else { (self.levels[y][x] - 16.67 / milliseconds as f32).max(0.0) }
+                    },
+                    Flicker::Stable(frames) if self.ages[y][x] >= frames => if pixel != 0 { 1.0 } else { 0.0 },
+                    Flicker::Stable(_) => self.levels[y][x]
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Filter, Flicker};
+    use hardware::{HEIGHT, WIDTH};
+
+    // The level of the top left pixel after each frame, lit or not
+    fn levels(mode: Flicker, frames: &[bool]) -> Vec<f32> {
+        let mut filter = Filter::new(mode);
+        frames.iter().map(|&lit| {
+            let mut gfx = [[0; WIDTH]; HEIGHT];
+            gfx[0][0] = lit as u8;
+            filter.push(&gfx);
+            filter.levels[0][0]
+        }).collect()
+    }
+
+    fn close(actual: Vec<f32>, expected: &[f32]) -> bool {
+        actual.len() == expected.len() && actual.iter().zip(expected).all(|(a, b)| (a - b).abs() < 0.001)
+    }
+
+    #[test]
+    fn off_shows_each_frame_as_it_is() {
+        assert_eq!(levels(Flicker::Off, &[true, false, true]), vec![1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn blend_averages_the_last_frames() {
+        let blended = levels(Flicker::Blend(2), &[true, false, false]);
+        assert!(close(blended.clone(), &[1.0, 0.5, 0.0]), "{:?}", blended);
+    }
+
+    #[test]
+    fn decay_fades_a_pixel_out_over_its_time() {
+        let decayed = levels(Flicker::Decay(100), &[true, false, false]);
+        assert!(close(decayed.clone(), &[1.0, 0.8333, 0.6666]), "{:?}", decayed);
+    }
+
+    #[test]
+    fn stable_waits_for_a_pixel_to_keep_its_value() {
+        assert_eq!(levels(Flicker::Stable(2), &[true, true, false]), vec![0.0, 1.0, 1.0]);
+        assert_eq!(levels(Flicker::Stable(2), &[true, true, false, false]).last(), Some(&0.0));
+    }
+}
@@ -0,0 +1,145 @@
+use flicker::Levels;
+use hardware::{Control, Gfx};
+#[cfg(any(feature = "piston", feature = "terminal"))]
+use hardware::{HEIGHT, WIDTH};
+#[cfg(any(feature = "piston", feature = "terminal"))]
+use std::io::{self, Write};
+
+// What the player does: holds or lets go of a control, which goes through the bindings,
+// or of one of the 16 keys itself, like on the on screen keypad
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Event {
+    Control(Control, bool),
+    Keypad(usize, bool)
+}
+
+// The screen through the flicker filter and the keys at the end of a frame, and the number of that frame
+pub type Update = (Gfx, Levels, [bool; 16], u64);
+
+// What shows before the first frame, a clear screen and no keys
+#[cfg(any(feature = "piston", feature = "terminal"))]
+pub const BLANK: Update = ([[0; WIDTH]; HEIGHT], [[0.0; WIDTH]; HEIGHT], [false; 16], 0);
+
+// Where the screen goes: a window, a terminal, a GIF or a test.
+// It gets at least every frame that changed, with the keys held and the number of the frame
+pub trait Display {
+    fn show(&mut self, update: &Update) -> Result<(), String>;
+}
+
+// Where the events come from: a window, a terminal, a script or a test, asked once a frame
+pub trait Input {
+    fn poll(&mut self) -> Vec<Event>;
+}
+
+// What makes the buzzer heard, told when the sound timer starts and when it stops
+#[cfg(any(feature = "piston", feature = "terminal"))]
+pub trait Audio {
+    fn buzz(&mut self, on: bool);
+}
+
+// No display shows nothing
+impl<T: Display> Display for Option<T> {
+    fn show(&mut self, update: &Update) -> Result<(), String> {
+        match *self {
+            Some(ref mut display) => display.show(update),
+            None => Ok(())
+        }
+    }
+}
+
+// No input sends nothing
+impl<T: Input> Input for Option<T> {
+    fn poll(&mut self) -> Vec<Event> {
+        match *self {
+            Some(ref mut input) => input.poll(),
+            None => vec![]
+        }
+    }
+}
+
+// The terminal bell, rung when the sound starts
+#[cfg(any(feature = "piston", feature = "terminal"))]
+pub struct Bell;
+
+#[cfg(any(feature = "piston", feature = "terminal"))]
+impl Audio for Bell {
+    fn buzz(&mut self, on: bool) {
+        if on {
+            print!("\x07");
+            let _ = io::stdout().flush();
+        }
+    }
+}
+
+// Shows nothing, sends nothing and is never heard, for runs nobody watches
+pub struct Nothing;
+
+impl Display for Nothing {
+    fn show(&mut self, _: &Update) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl Input for Nothing {
+    fn poll(&mut self) -> Vec<Event> {
+        vec![]
+    }
+}
+
+#[cfg(any(feature = "piston", feature = "terminal"))]
+impl Audio for Nothing {
+    fn buzz(&mut self, _: bool) {}
+}
+
+// Frontends for tests, which keep what they get and give what they were told to
+#[cfg(test)]
+pub mod test {
+    use super::{Display, Event, Input, Update};
+    use std::collections::VecDeque;
+    #[cfg(any(feature = "piston", feature = "terminal"))]
+    use {super::Audio, std::sync::{Arc, Mutex}};
+
+    // Every frame shown
+    #[derive(Default)]
+    pub struct Screens {
+        pub updates: Vec<Update>
+    }
+
+    impl Display for Screens {
+        fn show(&mut self, update: &Update) -> Result<(), String> {
+            self.updates.push(*update);
+            Ok(())
+        }
+    }
+
+    // The events of each frame in turn, none once they run out
+    pub struct Events {
+        frames: VecDeque<Vec<Event>>
+    }
+
+    impl Events {
+        pub fn new(frames: Vec<Vec<Event>>) -> Events {
+            Events { frames: frames.into() }
+        }
+    }
+
+    impl Input for Events {
+        fn poll(&mut self) -> Vec<Event> {
+            self.frames.pop_front().unwrap_or_default()
+        }
+    }
+
+    // Every start and stop of the buzzer, shared with a clone as the audio goes to the emulator thread
+    #[cfg(any(feature = "piston", feature = "terminal"))]
+    #[derive(Clone, Default)]
+    pub struct Buzzes {
+        pub buzzes: Arc<Mutex<Vec<bool>>>
+    }
+
+    #[cfg(any(feature = "piston", feature = "terminal"))]
+    impl Audio for Buzzes {
+        fn buzz(&mut self, on: bool) {
+            self.buzzes.lock().unwrap().push(on);
+        }
+    }
+}
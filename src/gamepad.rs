@@ -0,0 +1,128 @@
+use frontend::{Event, Input};
+use gilrs::{self, Axis, Button, EventType, GamepadId, Gilrs};
+use hardware::{Control, Direction};
+
+// The gamepads through gilrs, for any frontend. Buttons and axes get the numbers of
+// SDL game controllers, which gilrs maps every known pad to
+pub struct Gamepads {
+    gilrs: Gilrs,
+    // what each pad holds, let go when it is unplugged
+    held: Vec<(GamepadId, Control)>
+}
+
+impl Gamepads {
+    // None when the platform has no gamepads or they can't be read, the keyboard still plays
+    pub fn open() -> Option<Gamepads> {
+        match Gilrs::new() {
+            Ok(gilrs) => Some(Gamepads { gilrs, held: vec![] }),
+            Err(error) => {
+                eprintln!("gamepads are not available: {}", error);
+                None
+            }
+        }
+    }
+
+    fn control(&mut self, events: &mut Vec<Event>, id: GamepadId, control: Control, held: bool) {
+        let index = self.held.iter().position(|&(pad, other)| pad == id && other == control);
+        match (index, held) {
+            (None, true) => self.held.push((id, control)),
+            (Some(index), false) => {
+                self.held.remove(index);
+            },
+            _ => return
+        }
+        events.push(Event::Control(control, held));
+    }
+}
+
+impl Input for Gamepads {
+    fn poll(&mut self) -> Vec<Event> {
+        let mut events = vec![];
+        while let Some(gilrs::Event { id, event, .. }) = self.gilrs.next_event() {
+            match event {
+                EventType::ButtonPressed(button, _) | EventType::ButtonReleased(button, _) => {
+                    if let Some(number) = button_number(button) {
+                        let held = matches!(event, EventType::ButtonPressed(..));
+                        self.control(&mut events, id, Control::Button(number), held);
+                    }
+                },
+                EventType::AxisChanged(axis, value, _) => {
+                    for (control, held) in axis_controls(axis, value) {
+                        self.control(&mut events, id, control, held);
+                    }
+                },
+                EventType::Disconnected => {
+                    let held: Vec<Control> = self.held.iter().filter(|held| held.0 == id).map(|held| held.1).collect();
+                    for control in held {
+                        self.control(&mut events, id, control, false);
+                    }
+                },
+                _ => ()
+            }
+        }
+        events
+    }
+}
+
+fn button_number(button: Button) -> Option<u8> {
+    match button {
+        Button::South => Some(0),
+        Button::East => Some(1),
+        Button::West => Some(2),
+        Button::North => Some(3),
+        Button::Select => Some(4),
+        Button::Mode => Some(5),
+        Button::Start => Some(6),
+        Button::LeftThumb => Some(7),
+        Button::RightThumb => Some(8),
+        Button::LeftTrigger => Some(9),
+        Button::RightTrigger => Some(10),
+        Button::DPadUp => Some(11),
+        Button::DPadDown => Some(12),
+        Button::DPadLeft => Some(13),
+        Button::DPadRight => Some(14),
+        _ => None
+    }
+}
+
+// An axis past half way either side, gilrs has up positive where SDL has down.
+// Pads whose D-pad is an axis hold the directions of a hat
+fn axis_controls(axis: Axis, value: f32) -> Vec<(Control, bool)> {
+    let (positive, negative) = match axis {
+        Axis::LeftStickX => (Control::Axis(0, true), Control::Axis(0, false)),
+        Axis::LeftStickY => (Control::Axis(1, false), Control::Axis(1, true)),
+        Axis::RightStickX => (Control::Axis(2, true), Control::Axis(2, false)),
+        Axis::RightStickY => (Control::Axis(3, false), Control::Axis(3, true)),
+        Axis::DPadX => (Control::Hat(Direction::Right), Control::Hat(Direction::Left)),
+        Axis::DPadY => (Control::Hat(Direction::Up), Control::Hat(Direction::Down)),
+        _ => return vec![]
+    };
+    vec![(positive, value > 0.5), (negative, value < -0.5)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{axis_controls, button_number};
+    use gilrs::{Axis, Button};
+    use hardware::{Control, Direction};
+
+    #[test]
+    fn pushing_a_stick_up_is_the_negative_sdl_axis() {
+        assert_eq!(axis_controls(Axis::LeftStickY, 1.0), vec![(Control::Axis(1, false), true), (Control::Axis(1, true), false)]);
+        assert_eq!(axis_controls(Axis::LeftStickX, -0.8), vec![(Control::Axis(0, true), false), (Control::Axis(0, false), true)]);
+    }
+
+    #[test]
+    fn a_centered_axis_holds_nothing() {
+        assert!(axis_controls(Axis::RightStickY, 0.2).iter().all(|&(_, held)| !held));
+        assert_eq!(axis_controls(Axis::DPadY, 1.0)[0], (Control::Hat(Direction::Up), true));
+    }
+
+    #[test]
+    fn buttons_get_the_sdl_numbers_of_the_names() {
+        assert_eq!(button_number(Button::South), Some(0));
+        assert_eq!(button_number(Button::Start), Some(6));
+        assert_eq!(button_number(Button::DPadUp), Some(11));
+        assert_eq!(button_number(Button::Unknown), None);
+    }
+}
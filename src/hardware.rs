@@ -1,25 +1,62 @@
-use data;
-use piston_window::Key;
+use key::Key;
 
-pub type Gfx = [[u8; 64]; 32];
+// The size of the screen, every buffer of it is this big
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+
+pub type Gfx = [[u8; WIDTH]; HEIGHT];
+
+// A physical input that can be bound to one of the 16 keys
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Control {
+    Key(Key),
+    // a gamepad button by number
+    Button(u8),
+    // a direction of a hat, usually the D-pad
+    Hat(Direction),
+    // an axis past half way, towards the positive end or not
+    Axis(u8, bool),
+    // one of the 16 keys itself, pressed with the pointer or by a script
+    Keypad(usize)
+}
+
+// A hat held diagonally holds two directions
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right
+}
 
 pub struct Hardware {
     pub gfx: Gfx,
-    pub keys: [bool; 17]
+    pub keys: [bool; 16],
+    // from a physical control to one of the 16 keys, the first binding of a control wins
+    pub bindings: Vec<(Control, usize)>,
+    // controls held down, a key stays pressed while any control bound to it is held
+    pub held: Vec<Control>
 }
 
 impl Hardware {
-    pub fn press(&mut self, key: Key) {
-        self.keys[data::key_to_number(key)] = true;
+    pub fn press(&mut self, number: usize) {
+        self.keys[number] = true;
+    }
+
+    pub fn release(&mut self, number: usize) {
+        self.keys[number] = false;
     }
 
-    pub fn release(&mut self, key: Key) {
-        self.keys[data::key_to_number(key)] = false;
+    pub fn key_number(&self, control: Control) -> Option<usize> {
+        match control {
+            Control::Keypad(number) => Some(number),
+            _ => self.bindings.iter().find(|binding| binding.0 == control).map(|binding| binding.1)
+        }
     }
 
     pub fn clear(&mut self) {
-        for y in 0..32 {
-            for x in 0..64 {
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
                 self.gfx[y][x] = 0;
             }
         }
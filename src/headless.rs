@@ -0,0 +1,175 @@
+use chip8::Chip8;
+use data;
+use hardware::Control;
+use flicker::Filter;
+use frontend::{Display, Event, Input};
+use std::fs;
+
+// A hex key, or a key or gamepad name that goes through the bindings
+enum Target {
+    Hex(usize),
+    Control(Control)
+}
+
+// An input script, one event per line:
+// # frame action key
+// 60 press 5
+// 64 release 5
+// 90 press DPadUp
+pub struct Script {
+    events: Vec<(u32, Target, bool)>,
+    // the frame polled next
+    frame: u32
+}
+
+impl Script {
+    pub fn load(path: &str) -> Result<Script, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Script::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Script, String> {
+        let mut events = vec![];
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let event = match words.as_slice() {
+                [frame, action, key] => {
+                    let frame = frame.parse::<u32>().ok();
+                    let key = match usize::from_str_radix(key, 16) {
+                        Ok(number) if key.len() == 1 => Some(Target::Hex(number)),
+                        _ => data::controls_from_name(key).first().map(|&control| Target::Control(control))
+                    };
+                    let pressed = match *action {
+                        "press" => Some(true),
+                        "release" => Some(false),
+                        _ => None
+                    };
+                    match (frame, key, pressed) {
+                        (Some(frame), Some(key), Some(pressed)) => Some((frame, key, pressed)),
+                        _ => None
+                    }
+                },
+                _ => None
+            };
+
+            match event {
+                Some(event) => events.push(event),
+                None => return Err(format!("line {}: expected <frame> press|release <hex key or name>", i + 1))
+            }
+        }
+
+        events.sort_by_key(|event| event.0);
+        Ok(Script { events, frame: 0 })
+    }
+
+    pub fn empty() -> Script {
+        Script { events: vec![], frame: 0 }
+    }
+}
+
+// The events of each frame in turn
+impl Input for Script {
+    fn poll(&mut self) -> Vec<Event> {
+        let frame = self.frame;
+        self.frame += 1;
+        self.events.iter().filter(|event| event.0 == frame).map(|&(_, ref target, pressed)| match *target {
+            Target::Hex(number) => Event::Keypad(number, pressed),
+            Target::Control(control) => Event::Control(control, pressed)
+        }).collect()
+    }
+}
+
+// Runs as fast as it can, returns how many frames drew something, or the fault that stopped the CPU.
+// The input is asked before every frame and the display gets every frame with its 60 Hz time
+pub fn run(chip8: &mut Chip8, ipf: u32, frames: u32, input: &mut impl Input, display: &mut impl Display,
+           filter: &mut Filter) -> Result<u32, String> {
+    let mut drawn = 0;
+    for frame in 0..frames {
+        for event in input.poll() {
+            chip8.event(event);
+        }
+        if chip8.frame(ipf) {
+            drawn += 1;
+        }
+        filter.push(&chip8.hardware.gfx);
+        display.show(&(filter.colors, filter.levels, chip8.hardware.keys, frame as u64))?;
+        if let Some(fault) = chip8.cpu.fault {
+            return Err(fault.to_string());
+        }
+    }
+    Ok(drawn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use chip8::Chip8;
+    use cpu::Hack;
+    use flicker::{Filter, Flicker};
+    use frontend::Event;
+    use frontend::test::{Events, Screens};
+    use hardware::Control;
+    use key::Key;
+
+    fn chip8(rom: &[u8]) -> Chip8 {
+        Chip8::new(rom, Hack { shift_hack: false, memory_hack: false })
+    }
+
+    #[test]
+    fn every_frame_is_shown_with_the_keys_held() {
+        // JP 200
+        let mut chip8 = chip8(&[0x12, 0x00]);
+        let mut input = Events::new(vec![vec![], vec![Event::Keypad(5, true)], vec![], vec![Event::Keypad(5, false)]]);
+        let mut screens = Screens::default();
+        run(&mut chip8, 10, 5, &mut input, &mut screens, &mut Filter::new(Flicker::Off)).unwrap();
+
+        let frames: Vec<u64> = screens.updates.iter().map(|update| update.3).collect();
+        assert_eq!(frames, vec![0, 1, 2, 3, 4]);
+        let held: Vec<bool> = screens.updates.iter().map(|update| update.2[5]).collect();
+        assert_eq!(held, vec![false, true, true, false, false]);
+    }
+
+    #[test]
+    fn the_display_gets_what_is_drawn() {
+        // LDB 0, 0; FONT 0; DRW 0, 0, 5; JP 206
+        let mut chip8 = chip8(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06]);
+        let mut screens = Screens::default();
+        let drawn = run(&mut chip8, 10, 3, &mut Events::new(vec![]), &mut screens, &mut Filter::new(Flicker::Off));
+
+        assert_eq!(drawn, Ok(1));
+        let gfx = screens.updates.last().unwrap().0;
+        // the top of the 0 of the font, 0xF0
+        assert_eq!(gfx[0][..5], [1, 1, 1, 1, 0]);
+        assert_eq!(gfx[1][..5], [1, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn bound_controls_answer_a_wait_for_a_key() {
+        // GK 3; JP 202
+        let mut chip8 = chip8(&[0xF3, 0x0A, 0x12, 0x02]);
+        chip8.hardware.bindings = vec![(Control::Key(Key::Up), 2)];
+        let mut input = Events::new(vec![vec![], vec![], vec![Event::Control(Control::Key(Key::Up), true)]]);
+        run(&mut chip8, 10, 2, &mut input, &mut Screens::default(), &mut Filter::new(Flicker::Off)).unwrap();
+        assert!(chip8.cpu.halted);
+
+        run(&mut chip8, 10, 1, &mut input, &mut Screens::default(), &mut Filter::new(Flicker::Off)).unwrap();
+        assert!(!chip8.cpu.halted);
+        assert_eq!(chip8.cpu.v[3], 2);
+    }
+
+    #[test]
+    fn a_fault_ends_the_run_with_an_error() {
+        // CLS; RET
+        let mut chip8 = chip8(&[0x00, 0xE0, 0x00, 0xEE]);
+        let mut screens = Screens::default();
+        let result = run(&mut chip8, 10, 5, &mut Events::new(vec![]), &mut screens, &mut Filter::new(Flicker::Off));
+        assert_eq!(result, Err("return with an empty stack at 202".to_string()));
+        assert_eq!(screens.updates.len(), 1);
+    }
+}
@@ -0,0 +1,44 @@
+use serde::Deserialize;
+use serde_json;
+
+// The keys of a keyboard, named like piston's Key so the names in configs and the database stay the same.
+// Keys a frontend has no name for are Unknown
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Key {
+    Unknown,
+    Backspace, Tab, Return, Escape, Space, Delete, CapsLock,
+    Quote, Comma, Minus, Period, Slash, Semicolon, Equals, LeftBracket, Backslash, RightBracket, Backquote,
+    D0, D1, D2, D3, D4, D5, D6, D7, D8, D9,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    F13, F14, F15, F16, F17, F18, F19, F20, F21, F22, F23, F24,
+    PrintScreen, ScrollLock, Pause, Insert, Home, PageUp, End, PageDown,
+    Right, Left, Down, Up,
+    NumLockClear, NumPadDivide, NumPadMultiply, NumPadMinus, NumPadPlus, NumPadEnter, NumPadPeriod, NumPadEquals,
+    NumPad0, NumPad1, NumPad2, NumPad3, NumPad4, NumPad5, NumPad6, NumPad7, NumPad8, NumPad9,
+    LCtrl, LShift, LAlt, LGui, RCtrl, RShift, RAlt, RGui
+}
+
+impl Key {
+    // Like "Up" or "NumPad8"
+    pub fn from_name(name: &str) -> Option<Key> {
+        serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+    }
+
+    // The key that types a character without Shift, Unknown for any other character
+    #[cfg(feature = "terminal")]
+    pub fn from_char(c: char) -> Key {
+        match c {
+            'a'..='z' => Key::from_name(&c.to_ascii_uppercase().to_string()).unwrap_or(Key::Unknown),
+            '0'..='9' => Key::from_name(&format!("D{}", c)).unwrap_or(Key::Unknown),
+            _ => SYMBOLS.iter().find(|symbol| symbol.0 == c).map_or(Key::Unknown, |symbol| symbol.1)
+        }
+    }
+}
+
+#[cfg(feature = "terminal")]
+const SYMBOLS: [(char, Key); 13] = [
+    (' ', Key::Space), ('\t', Key::Tab), ('\'', Key::Quote), (',', Key::Comma), ('-', Key::Minus),
+    ('.', Key::Period), ('/', Key::Slash), (';', Key::Semicolon), ('=', Key::Equals), ('[', Key::LeftBracket),
+    ('\\', Key::Backslash), (']', Key::RightBracket), ('`', Key::Backquote)
+];
@@ -0,0 +1,39 @@
+use data::{self, Palette};
+use piston_window::*;
+
+// The keypad sits right of the screen, 4x4 cells of 8x8 CHIP-8 pixels
+pub const WIDTH: u32 = 32;
+const CELL: f64 = 8.0;
+
+// The key under a point, relative to the top left corner of the keypad
+pub fn cell(x: f64, y: f64, scale: f64) -> Option<usize> {
+    let (column, row) = ((x / (CELL * scale)).floor(), (y / (CELL * scale)).floor());
+    if column < 0.0 || row < 0.0 || column >= 4.0 || row >= 4.0 {
+        return None;
+    }
+    Some(data::PAD[row as usize * 4 + column as usize])
+}
+
+// Pressed keys are filled with the foreground, labels are drawn with the built in font
+pub fn render(context: &Context, graphics: &mut impl Graphics, keys: &[bool; 16], palette: Palette, [left, top]: [f64; 2], scale: f64) {
+    let font = data::fontset();
+    let (background, foreground) = (data::linear(palette[0]), data::linear(palette[1]));
+    let dim = [foreground[0], foreground[1], foreground[2], 0.15];
+
+    for (i, &number) in data::PAD.iter().enumerate() {
+        let x = left + (i % 4) as f64 * CELL * scale;
+        let y = top + (i / 4) as f64 * CELL * scale;
+        let (fill, label) = if keys[number] { (foreground, background) } else { (dim, foreground) };
+        rectangle(fill, [x + scale * 0.5, y + scale * 0.5, scale * 7.0, scale * 7.0], context.transform, graphics);
+
+        // the 4x5 digit of the font, centered in the cell
+        for (row, byte) in font[number * 5..number * 5 + 5].iter().enumerate() {
+            for column in 0..4 {
+                if byte >> (7 - column) & 1 == 1 {
+                    let rect = [x + (2 + column) as f64 * scale, y + (1.5 + row as f64) * scale, scale, scale];
+                    rectangle(label, rect, context.transform, graphics);
+                }
+            }
+        }
+    }
+}
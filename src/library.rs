@@ -0,0 +1,147 @@
+use database::{self, Database, Game};
+use std::path::{Path, PathBuf};
+
+// A game picked by its index in the list, its title or a path to any ROM
+pub fn resolve(database: &Database, name: &str) -> Result<PathBuf, String> {
+    let games = database.sorted();
+
+    if let Ok(index) = name.parse::<usize>() {
+        if let Some(path) = games.get(index).and_then(|(_, game)| database.path(game)) {
+            return Ok(path);
+        }
+    }
+
+    if Path::new(name).is_file() {
+        return Ok(PathBuf::from(name));
+    }
+
+    if let Some((_, game)) = games.iter().find(|(_, game)| game.title.eq_ignore_ascii_case(name)) {
+        return database.path(game).ok_or(format!("{} has no file", game.title));
+    }
+
+    let found = search(database, name);
+    match found.as_slice() {
+        [] => Err(format!("no game or file matches {}", name)),
+        [(_, game)] => database.path(game).ok_or(format!("{} has no file", game.title)),
+        _ => {
+            let titles: Vec<String> = found.iter().map(|(index, game)| format!("  {} {}", index, game.title)).collect();
+            Err(format!("{} matches several games:\n{}", name, titles.join("\n")))
+        }
+    }
+}
+
+// Every word has to show up in the title or the description,
+// title matches rank first and the letters of a word may be spread over the title
+pub fn search<'a>(database: &'a Database, text: &str) -> Vec<(usize, &'a Game)> {
+    let words: Vec<String> = text.split_whitespace().map(|word| word.to_lowercase()).collect();
+    let mut found = vec![];
+
+    for (index, (_, game)) in database.sorted().into_iter().enumerate() {
+        let title = game.title.to_lowercase();
+        let description = strip_html(&game.description).to_lowercase();
+        let mut score = 0;
+
+        for word in words.iter() {
+            score += if title.contains(word.as_str()) {
+                if title.split_whitespace().any(|x| x.starts_with(word.as_str())) { 4 } else { 3 }
+            } else if description.contains(word.as_str()) {
+                2
+            } else if subsequence(word, &title) {
+                1
+            } else {
+                score = 0;
+                break;
+            };
+        }
+
+        if score > 0 {
+            found.push((score, index, game));
+        }
+    }
+
+    found.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    found.into_iter().map(|(_, index, game)| (index, game)).collect()
+}
+
+fn subsequence(word: &str, text: &str) -> bool {
+    let mut chars = text.chars();
+    word.chars().all(|c| chars.any(|x| x == c))
+}
+
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut tag = None;
+
+    for c in html.chars() {
+        match (c, tag.as_mut()) {
+            ('<', None) => tag = Some(String::new()),
+            ('>', Some(name)) => {
+                let name = name.trim_start_matches('/').trim_end_matches('/').trim().to_lowercase();
+                if name == "br" || name == "p" {
+                    text.push('\n');
+                }
+                tag = None;
+            },
+            (c, Some(name)) => name.push(c),
+            (c, None) => text.push(c)
+        }
+    }
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+pub fn info(database: &Database, rom: &Path, bytes: &[u8]) -> String {
+    let hash = database::hash(bytes);
+    let game = match database.games.get(&hash) {
+        Some(game) => game,
+        None => return format!("{}\nsha1: {}\nnot in {}\n", rom.display(), hash, database::PATH)
+    };
+
+    let mut text = format!("{}\n", game.title);
+    text += &format!("file: {}\n", rom.display());
+    text += &format!("sha1: {}\n", hash);
+    for line in details(game) {
+        text += &format!("{}\n", line);
+    }
+    text += &format!("\n{}\n", strip_html(&game.description).trim());
+    text
+}
+
+// The settings the database has for a game, a line each
+pub fn details(game: &Game) -> Vec<String> {
+    let mut lines = vec![];
+    if let Some(platform) = game.platform {
+        lines.push(format!("platform: {}", platform));
+    }
+    let quirks = game.quirks.clone().unwrap_or_default();
+    let mut names = vec![];
+    if quirks.shift.unwrap_or(false) {
+        names.push("shift");
+    }
+    if quirks.load_store.unwrap_or(false) {
+        names.push("loadStore");
+    }
+    lines.push(format!("quirks: {}", if names.is_empty() { "none".to_string() } else { names.join(", ") }));
+    if let Some(ipf) = game.ipf {
+        lines.push(format!("instructions per frame: {}", ipf));
+    }
+    if let Some(ref keys) = game.keys {
+        let keys: Vec<String> = keys.iter().map(|(key, number)| format!("{} = {}", key, number)).collect();
+        lines.push(format!("keys: {}", keys.join(", ")));
+    }
+    if let Some(ref palette) = game.palette {
+        lines.push(format!("palette: {}", palette));
+    }
+    if let Some(rotation) = game.rotation {
+        lines.push(format!("rotation: {}", rotation));
+    }
+    if let Some(flip) = game.flip {
+        lines.push(format!("flip: {:?}", flip).to_lowercase());
+    }
+    lines
+}
@@ -0,0 +1,241 @@
+use chip8::Chip8;
+use config::Config;
+use data::{Palette, BLACK};
+use database::{Database, Game};
+use flicker::{Filter, Flicker};
+use frontend::Nothing;
+use headless;
+use library;
+use piston_window::*;
+use settings::{Overrides, Settings};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use text;
+use upscale::Upscaler;
+use video::{self, Frame, Options};
+use window::{self, Window};
+
+// The menu is drawn on a 320x160 picture, scaled up like the screen
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 160;
+// titles in the list at once
+const ROWS: usize = 16;
+// the list is on the left, the selected game on the right
+const LIST: u32 = 124;
+// thumbnails are what a game shows after 5 seconds
+const FRAMES: u32 = 300;
+
+// The games of the database to pick from, with a description and a thumbnail of the selected one
+pub struct Menu<'a> {
+    database: &'a Database,
+    config: &'a Config,
+    games: Vec<(&'a String, &'a Game)>,
+    selected: usize,
+    // the first title shown and the first line of the description
+    first: usize,
+    scroll: usize,
+    thumbnails: HashMap<String, Option<Frame>>
+}
+
+impl<'a> Menu<'a> {
+    pub fn new(database: &'a Database, config: &'a Config) -> Menu<'a> {
+        Menu { database, config, games: database.sorted(), selected: 0, first: 0, scroll: 0, thumbnails: HashMap::new() }
+    }
+
+    // The ROM to play once Enter is pressed or a ROM is dropped, None on Escape or when the window closes
+    pub fn pick(&mut self, window: &mut Window) -> Result<Option<PathBuf>, String> {
+        let palette = Settings::from_game("", None, self.config, &Overrides::default()).colors();
+        window.window.set_title("chip-8".to_string());
+        let mut texture_context = window.window.create_texture_context();
+        let mut texture: Option<G2dTexture> = None;
+        let (mut scale, mut origin) = window::fit(window.size(), [WIDTH as f64, HEIGHT as f64], false);
+        let mut cursor = [0.0, 0.0];
+        let mut dirty = true;
+
+        while let Some(event) = window.window.next() {
+            window.handle(&event);
+            let before = (self.selected, self.scroll);
+            let count = self.games.len();
+
+            if let Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(key), state: ButtonState::Press, .. }), _) = event {
+                match key {
+                    Key::Escape => return Ok(None),
+                    Key::Return | Key::NumPadEnter => {
+                        if let Some(path) = self.path() {
+                            return Ok(Some(path));
+                        }
+                    },
+                    Key::Up => self.select(self.selected.saturating_sub(1)),
+                    Key::Down => self.select(self.selected + 1),
+                    Key::PageUp => self.select(self.selected.saturating_sub(ROWS)),
+                    Key::PageDown => self.select(self.selected + ROWS),
+                    Key::Home => self.select(0),
+                    Key::End => self.select(count.saturating_sub(1)),
+                    Key::Left => self.scroll = self.scroll.saturating_sub(1),
+                    Key::Right => self.scroll += 1,
+                    _ => ()
+                }
+            }
+            match event {
+                // typing a letter goes to the next title starting with it
+                Event::Input(Input::Text(ref typed), _) => {
+                    if let Some(c) = typed.chars().next().map(|c| c.to_ascii_lowercase()) {
+                        let next = (1..=count).map(|i| (self.selected + i) % count)
+                            .find(|&i| self.games[i].1.title.to_lowercase().starts_with(c));
+                        if let Some(next) = next {
+                            self.select(next);
+                        }
+                    }
+                },
+                Event::Input(Input::Move(Motion::MouseCursor(position)), _) => cursor = position,
+                Event::Input(Input::Move(Motion::MouseScroll([_, y])), _) => {
+                    if y > 0.0 {
+                        self.select(self.selected.saturating_sub(1));
+                    } else if y < 0.0 {
+                        self.select(self.selected + 1);
+                    }
+                },
+                // a click picks a title, a click on the picked one plays it
+                Event::Input(Input::Button(ButtonArgs { button: Button::Mouse(MouseButton::Left), state: ButtonState::Press, .. }), _) => {
+                    let [x, y] = [(cursor[0] - origin[0]) / scale, (cursor[1] - origin[1]) / scale];
+                    if x >= 0.0 && x < LIST as f64 && y >= 2.0 {
+                        let row = self.first + ((y - 2.0) / text::HEIGHT as f64) as usize;
+                        if row < count && row < self.first + ROWS {
+                            if row == self.selected {
+                                if let Some(path) = self.path() {
+                                    return Ok(Some(path));
+                                }
+                            }
+                            self.select(row);
+                        }
+                    }
+                },
+                Event::Input(Input::FileDrag(FileDrag::Drop(ref path)), _) => return Ok(Some(path.clone())),
+                Event::Input(Input::Resize(_), _) => {
+                    let fitted = window::fit(window.size(), [WIDTH as f64, HEIGHT as f64], false);
+                    scale = fitted.0;
+                    origin = fitted.1;
+                },
+                _ => ()
+            }
+            if (self.selected, self.scroll) != before {
+                dirty = true;
+            }
+
+            if dirty && event.render_args().is_some() {
+                let frame = self.render(palette);
+                texture = Some(window::upload(&mut texture_context, texture, &frame)?);
+                dirty = false;
+            }
+            window.window.draw_2d(&event, |context, graphics, device| {
+                texture_context.encoder.flush(device);
+                clear(BLACK, graphics);
+                if let Some(ref texture) = texture {
+                    image(texture, context.transform.trans(origin[0], origin[1]).zoom(scale), graphics);
+                }
+            });
+        }
+
+        Ok(None)
+    }
+
+    fn select(&mut self, index: usize) {
+        let last = self.games.len().saturating_sub(1);
+        self.selected = index.min(last);
+        self.scroll = 0;
+        // the list follows the selection
+        if self.selected < self.first {
+            self.first = self.selected;
+        } else if self.selected >= self.first + ROWS {
+            self.first = self.selected + 1 - ROWS;
+        }
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        self.games.get(self.selected).and_then(|(_, game)| self.database.path(game))
+    }
+
+    // Made the first time a game is selected and kept
+    fn thumbnail(&mut self) -> Option<&Frame> {
+        let (hash, game) = *self.games.get(self.selected)?;
+        let (database, config) = (self.database, self.config);
+        self.thumbnails.entry(hash.clone()).or_insert_with(|| {
+            let bytes = fs::read(database.path(game)?).ok()?;
+            let settings = Settings::from_game(hash, Some(game), config, &Overrides::default());
+            // as big as fits 128x64, turned games are taller
+            let (width, height) = settings.orientation.screen();
+            thumbnail(&bytes, &settings, FRAMES, (128 / width).min(64 / height).max(1))
+        }).as_ref()
+    }
+
+    fn render(&mut self, palette: Palette) -> Frame {
+        let mut frame = Frame::new(WIDTH, HEIGHT);
+        let background = video::bytes(palette[0]);
+        let foreground = video::bytes(palette[1]);
+        let dim = video::bytes(video::mix(palette[0], palette[1], 0.5));
+        let bar = video::bytes(video::mix(palette[0], palette[1], 0.25));
+        frame.fill(0, 0, WIDTH, HEIGHT, background);
+
+        for (row, (_, game)) in self.games.iter().enumerate().skip(self.first).take(ROWS) {
+            let y = 2 + (row - self.first) as u32 * text::HEIGHT;
+            if row == self.selected {
+                frame.fill(0, y - 1, LIST - 2, text::HEIGHT, bar);
+            }
+            let title: String = game.title.chars().take((LIST / text::WIDTH) as usize - 1).collect();
+            text::draw(&mut frame, &title, 2, y, foreground);
+        }
+        frame.fill(LIST, 0, 1, HEIGHT - 12, dim);
+        text::draw(&mut frame, "arrows choose, Enter plays, Esc quits", 2, HEIGHT - 9, dim);
+
+        let game = match self.games.get(self.selected) {
+            Some(&(_, game)) => game,
+            None => {
+                text::draw(&mut frame, "no games in the database", LIST + 4, 2, foreground);
+                return frame;
+            }
+        };
+        let left = LIST + 4;
+        let columns = ((WIDTH - left) / text::WIDTH) as usize;
+        let title: String = game.title.chars().take(columns).collect();
+        text::draw(&mut frame, &title, left, 2, foreground);
+
+        // the thumbnail centered in a 128x64 box
+        let thumbnail = self.thumbnail().cloned();
+        match thumbnail {
+            Some(thumbnail) => {
+                let x = left + (128 - thumbnail.width.min(128)) / 2;
+                let y = 12 + (64 - thumbnail.height.min(64)) / 2;
+                frame.blit(&thumbnail, x, y);
+            },
+            None => text::draw(&mut frame, "no preview", left + 34, 40, dim)
+        }
+
+        // the settings of the game and its description, Left and Right scroll them
+        let mut lines: Vec<String> = vec![];
+        for line in library::details(game) {
+            lines.extend(text::wrap(&line, columns));
+        }
+        lines.push(String::new());
+        lines.extend(text::wrap(library::strip_html(&game.description).trim(), columns));
+        let rows = ((HEIGHT - 12 - 80) / text::HEIGHT) as usize;
+        self.scroll = self.scroll.min(lines.len().saturating_sub(rows));
+        for (i, line) in lines.iter().skip(self.scroll).take(rows).enumerate() {
+            let color = if i + self.scroll < lines.len() - 1 && line.contains(": ") { dim } else { foreground };
+            text::draw(&mut frame, line, left, 80 + i as u32 * text::HEIGHT, color);
+        }
+        if self.scroll + rows < lines.len() {
+            text::draw(&mut frame, ">", WIDTH - text::WIDTH, HEIGHT - 12 - text::HEIGHT, dim);
+        }
+        frame
+    }
+}
+
+// What a game shows after running for some frames without input, None if it faults
+fn thumbnail(bytes: &[u8], settings: &Settings, frames: u32, scale: u32) -> Option<Frame> {
+    let mut chip8 = Chip8::new(bytes, settings.hack());
+    let mut filter = Filter::new(Flicker::Off);
+    headless::run(&mut chip8, settings.ipf, frames, &mut Nothing, &mut Nothing, &mut filter).ok()?;
+    let options = Options { gap: 0, grid: false, upscaler: Upscaler::Nearest, effects: vec![], ..settings.video(scale) };
+    Some(video::render(&filter.colors, &filter.levels, settings.colors(), &options))
+}
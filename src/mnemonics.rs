@@ -0,0 +1,97 @@
+// The instructions the assembler reads and the disassembler writes
+
+// The name of each kind of argument, where its bits sit in the opcode and the largest value they hold
+pub struct Field {
+    pub kind: &'static str,
+    pub shift: u16,
+    pub max: u16
+}
+
+const X: Field = Field { kind: "register", shift: 8, max: 0xF };
+const Y: Field = Field { kind: "register", shift: 4, max: 0xF };
+const BYTE: Field = Field { kind: "byte", shift: 0, max: 0xFF };
+const ADDRESS: Field = Field { kind: "address", shift: 0, max: 0xFFF };
+// DXYN only has a nibble for the height, N = 0 being the 16x16 SCHIP sprite
+const NIBBLE: Field = Field { kind: "nibble", shift: 0, max: 0xF };
+
+pub struct Mnemonic {
+    pub name: &'static str,
+    // The opcode with every argument set to 0
+    pub opcode: u16,
+    pub fields: &'static [Field]
+}
+
+const fn mnemonic(name: &'static str, opcode: u16, fields: &'static [Field]) -> Mnemonic {
+    Mnemonic { name, opcode, fields }
+}
+
+pub const MNEMONICS: &[Mnemonic] = &[
+    mnemonic("CLS", 0x00E0, &[]),
+    mnemonic("RET", 0x00EE, &[]),
+    mnemonic("JP", 0x1000, &[ADDRESS]),
+    mnemonic("CALL", 0x2000, &[ADDRESS]),
+    mnemonic("SEB", 0x3000, &[X, BYTE]),
+    mnemonic("SNEB", 0x4000, &[X, BYTE]),
+    mnemonic("SE", 0x5000, &[X, Y]),
+    mnemonic("LDB", 0x6000, &[X, BYTE]),
+    mnemonic("ADDB", 0x7000, &[X, BYTE]),
+    mnemonic("LD", 0x8000, &[X, Y]),
+    mnemonic("OR", 0x8001, &[X, Y]),
+    mnemonic("AND", 0x8002, &[X, Y]),
+    mnemonic("XOR", 0x8003, &[X, Y]),
+    mnemonic("ADD", 0x8004, &[X, Y]),
+    mnemonic("SUB", 0x8005, &[X, Y]),
+    mnemonic("SHR", 0x8006, &[X, Y]),
+    mnemonic("SUBN", 0x8007, &[X, Y]),
+    mnemonic("SHL", 0x800E, &[X, Y]),
+    mnemonic("SNE", 0x9000, &[X, Y]),
+    mnemonic("LDI", 0xA000, &[ADDRESS]),
+    mnemonic("JPP", 0xB000, &[ADDRESS]),
+    mnemonic("RND", 0xC000, &[X, BYTE]),
+    mnemonic("DRW", 0xD000, &[X, Y, NIBBLE]),
+    mnemonic("SKP", 0xE09E, &[X]),
+    mnemonic("SKNP", 0xE0A1, &[X]),
+    mnemonic("GDT", 0xF007, &[X]),
+    mnemonic("GK", 0xF00A, &[X]),
+    mnemonic("SDT", 0xF015, &[X]),
+    mnemonic("SST", 0xF018, &[X]),
+    mnemonic("ADDI", 0xF01E, &[X]),
+    mnemonic("FONT", 0xF029, &[X]),
+    mnemonic("BCD", 0xF033, &[X]),
+    // FX55 reg_dump stores V0 to VX at I, FX65 reg_load reads them back
+    mnemonic("DUMP", 0xF055, &[X]),
+    mnemonic("LOAD", 0xF065, &[X])
+];
+
+impl Mnemonic {
+    pub fn encode(&self, args: &[u16]) -> u16 {
+        self.fields.iter().zip(args).fold(self.opcode, |opcode, (field, arg)| opcode | arg << field.shift)
+    }
+
+    fn matches(&self, opcode: u16) -> bool {
+        let arguments = self.fields.iter().fold(0, |mask, field| mask | field.max << field.shift);
+        opcode & !arguments == self.opcode
+    }
+
+    fn arguments(&self, opcode: u16) -> impl Iterator<Item = u16> + '_ {
+        self.fields.iter().map(move |field| opcode >> field.shift & field.max)
+    }
+}
+
+pub fn find(name: &str) -> Option<&'static Mnemonic> {
+    MNEMONICS.iter().find(|mnemonic| mnemonic.name == name)
+}
+
+// The instruction as the assembler reads it, empty for opcodes that are not in the table
+pub fn decode(opcode: u16) -> String {
+    let mnemonic = match MNEMONICS.iter().find(|mnemonic| mnemonic.matches(opcode)) {
+        Some(mnemonic) => mnemonic,
+        None => return "".to_string()
+    };
+    let args: Vec<String> = mnemonic.arguments(opcode).map(|arg| arg.to_string()).collect();
+    if args.is_empty() {
+        mnemonic.name.to_string()
+    } else {
+        format!("{} {}", mnemonic.name, args.join(", "))
+    }
+}
@@ -0,0 +1,136 @@
+use hardware::{HEIGHT, WIDTH};
+use serde::{Deserialize, Serialize};
+use video::Frame;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Flip {
+    Off,
+    // left and right swap
+    Horizontal,
+    // top and bottom swap
+    Vertical
+}
+
+// How the screen is turned for games made to be played on their side,
+// flipped first and then rotated clockwise
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Orientation {
+    pub rotation: u16,
+    pub flip: Flip
+}
+
+// The hex keys games use the most for up, down, left and right
+const DIRECTIONS: [(usize, [i32; 2]); 4] = [(2, [0, -1]), (8, [0, 1]), (4, [-1, 0]), (6, [1, 0])];
+
+impl Orientation {
+    pub fn new() -> Orientation {
+        Orientation { rotation: 0, flip: Flip::Off }
+    }
+
+    pub fn parse_flip(name: &str) -> Option<Flip> {
+        match name {
+            "off" => Some(Flip::Off),
+            "horizontal" => Some(Flip::Horizontal),
+            "vertical" => Some(Flip::Vertical),
+            _ => None
+        }
+    }
+
+    // The size of a width x height screen once turned
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        if self.rotation % 180 == 90 { (height, width) } else { (width, height) }
+    }
+
+    // The CHIP-8 screen as it is shown
+    pub fn screen(&self) -> (u32, u32) {
+        self.size(WIDTH as u32, HEIGHT as u32)
+    }
+
+    pub fn apply(&self, frame: &Frame) -> Frame {
+        if *self == Orientation::new() {
+            return frame.clone();
+        }
+        let (width, height) = self.size(frame.width, frame.height);
+        let mut turned = Frame::new(width, height);
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let (x1, y1) = match self.flip {
+                    Flip::Off => (x, y),
+                    Flip::Horizontal => (frame.width - 1 - x, y),
+                    Flip::Vertical => (x, frame.height - 1 - y)
+                };
+                let (x2, y2) = match self.rotation {
+                    90 => (frame.height - 1 - y1, x1),
+                    180 => (frame.width - 1 - x1, frame.height - 1 - y1),
+                    270 => (y1, frame.width - 1 - x1),
+                    _ => (x1, y1)
+                };
+                turned.set(x2, y2, frame.get(x, y));
+            }
+        }
+        turned
+    }
+
+    // For every hex key, the one to press so a direction on the turned screen
+    // moves the same way in the game
+    pub fn directions(&self) -> [usize; 16] {
+        let mut keys = [0; 16];
+        for (number, key) in keys.iter_mut().enumerate() {
+            *key = number;
+        }
+        for &(number, [x, y]) in DIRECTIONS.iter() {
+            // undo the rotation and then the flip
+            let [x, y] = match self.rotation {
+                90 => [y, -x],
+                180 => [-x, -y],
+                270 => [-y, x],
+                _ => [x, y]
+            };
+            let [x, y] = match self.flip {
+                Flip::Off => [x, y],
+                Flip::Horizontal => [-x, y],
+                Flip::Vertical => [x, -y]
+            };
+            if let Some(&(game, _)) = DIRECTIONS.iter().find(|direction| direction.1 == [x, y]) {
+                keys[number] = game;
+            }
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Flip, Orientation};
+    use video::Frame;
+
+    const A: [u8; 4] = [255, 0, 0, 255];
+    const B: [u8; 4] = [0, 0, 255, 255];
+
+    // A 2x1 frame turned, as its size and its pixels row by row
+    fn turn(rotation: u16, flip: Flip) -> (u32, u32, Vec<[u8; 4]>) {
+        let mut frame = Frame::new(2, 1);
+        frame.set(0, 0, A);
+        frame.set(1, 0, B);
+        let turned = Orientation { rotation, flip }.apply(&frame);
+        let pixels = turned.pixels.chunks(4).map(|p| [p[0], p[1], p[2], p[3]]).collect();
+        (turned.width, turned.height, pixels)
+    }
+
+    #[test]
+    fn rotations_turn_the_frame_clockwise() {
+        assert_eq!(turn(0, Flip::Off), (2, 1, vec![A, B]));
+        assert_eq!(turn(90, Flip::Off), (1, 2, vec![A, B]));
+        assert_eq!(turn(180, Flip::Off), (2, 1, vec![B, A]));
+        assert_eq!(turn(270, Flip::Off), (1, 2, vec![B, A]));
+    }
+
+    #[test]
+    fn flips_mirror_the_frame_before_it_is_rotated() {
+        assert_eq!(turn(0, Flip::Horizontal), (2, 1, vec![B, A]));
+        assert_eq!(turn(0, Flip::Vertical), (2, 1, vec![A, B]));
+        assert_eq!(turn(90, Flip::Horizontal), (1, 2, vec![B, A]));
+        assert_eq!(turn(270, Flip::Vertical), (1, 2, vec![B, A]));
+    }
+}
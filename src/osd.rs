@@ -0,0 +1,141 @@
+use data::Palette;
+use session::Stats;
+use std::time::{Duration, Instant};
+use text;
+use video::{self, Frame};
+
+// How long a message stays and how many show at once, the newest at the bottom
+const SHOWN: Duration = Duration::from_secs(2);
+const MESSAGES: usize = 4;
+
+// Messages over the screen for a moment and the status bar under them,
+// drawn on a transparent frame laid over the screen
+pub struct Osd {
+    messages: Vec<(String, Instant)>,
+    pub status: bool,
+    // what was counted since the start of the second, and the rates of the last one
+    second: Instant,
+    frames: u32,
+    instructions: u64,
+    fps: u32,
+    ips: u64,
+    sounding: bool,
+    // whether the frame has to be drawn again
+    changed: bool
+}
+
+impl Osd {
+    pub fn new(status: bool) -> Osd {
+        Osd {
+            messages: vec![],
+            status,
+            second: Instant::now(),
+            frames: 0,
+            instructions: 0,
+            fps: 0,
+            ips: 0,
+            sounding: false,
+            changed: true
+        }
+    }
+
+    // A message already shown starts its time again instead of showing twice
+    pub fn show(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        self.messages.retain(|(shown, _)| *shown != message);
+        self.messages.push((message, Instant::now()));
+        if self.messages.len() > MESSAGES {
+            self.messages.remove(0);
+        }
+        self.changed = true;
+    }
+
+    pub fn toggle(&mut self) {
+        self.status = !self.status;
+        self.changed = true;
+    }
+
+    // Counts a frame drawn by the window, the rates change once a second
+    pub fn frame(&mut self, stats: Stats) {
+        if let Some((address, opcode)) = stats.unknown {
+            self.show(format!("unknown opcode {:04X} at {:03X}", opcode, address));
+        }
+        // shown for as long as they last, F8 gets past a fault
+        if let Some(fault) = stats.fault {
+            self.show(format!("stopped: {}, F8 resets", fault));
+        }
+        if stats.crashed {
+            self.show("the emulator crashed, Escape leaves");
+        }
+        if stats.sounding != self.sounding {
+            self.sounding = stats.sounding;
+            self.changed |= self.status;
+        }
+
+        self.frames += 1;
+        let elapsed = self.second.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            // a reset or another game counts from 0 again
+            let instructions = stats.instructions.saturating_sub(self.instructions);
+            self.fps = (self.frames as f64 / elapsed.as_secs_f64()).round() as u32;
+            self.ips = (instructions as f64 / elapsed.as_secs_f64()).round() as u64;
+            self.second = Instant::now();
+            self.frames = 0;
+            self.instructions = stats.instructions;
+            self.changed |= self.status;
+        }
+    }
+
+    // Whether anything is shown
+    pub fn visible(&self) -> bool {
+        self.status || !self.messages.is_empty()
+    }
+
+    // Whether the frame has to be drawn again, old messages go first
+    pub fn changed(&mut self) -> bool {
+        let count = self.messages.len();
+        self.messages.retain(|(_, shown)| shown.elapsed() < SHOWN);
+        self.changed || self.messages.len() != count
+    }
+
+    // The messages at the top left and the status bar along the bottom, in the colors of the palette
+    // on a see-through background, width x height in text pixels
+    pub fn render(&mut self, width: u32, height: u32, title: &str, palette: Palette) -> Frame {
+        let mut frame = Frame::new(width, height);
+        let foreground = video::bytes(palette[1]);
+        let [r, g, b, _] = video::bytes(palette[0]);
+        let background = [r, g, b, 0xC0];
+        let columns = (width / text::WIDTH).saturating_sub(1) as usize;
+
+        for (i, (message, _)) in self.messages.iter().enumerate() {
+            let message: String = message.chars().take(columns).collect();
+            let y = i as u32 * (text::HEIGHT + 1);
+            frame.fill(0, y, message.chars().count() as u32 * text::WIDTH + 3, text::HEIGHT + 1, background);
+            text::draw(&mut frame, &message, 2, y + 2, foreground);
+        }
+
+        if self.status {
+            let top = height.saturating_sub(text::HEIGHT + 1);
+            frame.fill(0, top, width, text::HEIGHT + 1, background);
+            let rates = format!("{} fps  {} ips  ", self.fps, self.ips);
+            // the title gets what is left, the sound sign the last columns
+            let room = columns.saturating_sub(rates.len() + 2);
+            let line = rates + &title.chars().take(room).collect::<String>();
+            text::draw(&mut frame, &line, 2, top + 2, foreground);
+            if self.sounding {
+                speaker(&mut frame, width - text::WIDTH - 2, top + 2, foreground);
+            }
+        }
+
+        self.changed = false;
+        frame
+    }
+}
+
+// A 5x7 speaker, the sign of the sound timer running
+fn speaker(frame: &mut Frame, x: u32, y: u32, color: [u8; 4]) {
+    frame.fill(x, y + 2, 2, 3, color);
+    frame.fill(x + 2, y + 1, 1, 5, color);
+    frame.fill(x + 3, y, 1, 7, color);
+    frame.fill(x + 4, y + 3, 1, 1, color);
+}
@@ -0,0 +1,114 @@
+use data::Palette;
+use flicker::Levels;
+use frontend::{Display, Update};
+use gif::{self, Encoder, Repeat, SetParameter};
+use hardware::Gfx;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use video::{self, Frame, Options};
+
+// Browsers show faster frames at 10 centiseconds, so nothing lasts less than 2
+const SHORTEST: u64 = 2;
+
+// Records the screen to an animated GIF as the frames come, in 60 Hz time:
+// a screen that does not change makes the last picture last longer,
+// and pictures that would last less than 2 centiseconds give way to the next one
+pub struct Recorder {
+    encoder: Encoder<BufWriter<File>>,
+    path: PathBuf,
+    palette: Palette,
+    options: Options,
+    // the screen last pushed and the 60 Hz frame the recording started on
+    screen: Option<(Gfx, Levels)>,
+    first: u64,
+    // the picture not written yet, since its length is known only once the next one comes
+    pending: Option<(Frame, u64)>,
+    // the first write that failed, the recording stops there
+    error: Option<String>
+}
+
+impl Recorder {
+    // The pictures have the size of the options, the palette and the look stay for the whole recording
+    pub fn start(path: &Path, palette: Palette, options: Options, frame: u64) -> Result<Recorder, String> {
+        let error = |e: ::std::io::Error| format!("{}: {}", path.display(), e);
+        let (width, height) = options.orientation.screen();
+        let [width, height] = [width * options.scale, height * options.scale];
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(format!("{}: {}x{} is too big for a GIF", path.display(), width, height));
+        }
+        let file = File::create(path).map_err(error)?;
+        let mut encoder = Encoder::new(BufWriter::new(file), width as u16, height as u16, &[]).map_err(error)?;
+        encoder.set(Repeat::Infinite).map_err(error)?;
+
+        Ok(Recorder { encoder, path: path.to_path_buf(), palette, options, screen: None, first: frame, pending: None, error: None })
+    }
+
+    // Ends the recording with the last picture shown until the 60 Hz frame
+    pub fn finish(mut self, frame: u64) -> Result<PathBuf, String> {
+        if let Some((pending, start)) = self.pending.take() {
+            self.write(&pending, start, frame);
+        }
+        match self.error {
+            Some(error) => Err(error),
+            // the encoder writes the end of the file when dropped
+            None => Ok(self.path)
+        }
+    }
+
+    fn write(&mut self, picture: &Frame, start: u64, end: u64) {
+        let delay = (self.centiseconds(end) - self.centiseconds(start)).max(SHORTEST);
+        let mut frame = indexed(picture);
+        frame.delay = delay.min(u16::MAX as u64) as u16;
+        if let Err(error) = self.encoder.write_frame(&frame) {
+            self.error = Some(format!("{}: {}", self.path.display(), error));
+        }
+    }
+
+    // Since the start, rounded down so the lengths add up to the exact time
+    fn centiseconds(&self, frame: u64) -> u64 {
+        frame.saturating_sub(self.first) * 100 / 60
+    }
+}
+
+impl Display for Recorder {
+    // The screen as it is from the 60 Hz frame on, a failed write shows when the recording finishes
+    fn show(&mut self, &(ref colors, ref levels, _, frame): &Update) -> Result<(), String> {
+        if self.error.is_some() || self.screen == Some((*colors, *levels)) {
+            return Ok(());
+        }
+        self.screen = Some((*colors, *levels));
+        let picture = video::render(colors, levels, self.palette, &self.options);
+        self.pending = match self.pending.take() {
+            Some((pending, start)) if self.centiseconds(frame) - self.centiseconds(start) >= SHORTEST => {
+                self.write(&pending, start, frame);
+                Some((picture, frame))
+            },
+            // too short to show, the new picture takes its place and its time
+            Some((_, start)) => Some((picture, start)),
+            None => Some((picture, frame))
+        };
+        Ok(())
+    }
+}
+
+// The picture with its own colors when it has up to 256 of them, which the palettes alone always do,
+// and quantized when effects or upscalers made more
+fn indexed(picture: &Frame) -> gif::Frame<'static> {
+    let (width, height) = (picture.width as u16, picture.height as u16);
+    let mut colors: Vec<[u8; 3]> = vec![];
+    let mut indices = Vec::with_capacity(picture.pixels.len() / 4);
+    for pixel in picture.pixels.chunks(4) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let index = match colors.iter().position(|&x| x == color) {
+            Some(index) => index,
+            None if colors.len() < 256 => {
+                colors.push(color);
+                colors.len() - 1
+            },
+            None => return gif::Frame::from_rgba_speed(width, height, &mut picture.pixels.clone(), 10)
+        };
+        indices.push(index as u8);
+    }
+    gif::Frame::from_palette_pixels(width, height, &indices, &colors.concat(), None)
+}
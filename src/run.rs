@@ -1,73 +1,375 @@
 use chip8::Chip8;
-use cpu::Hack;
-use data;
+use data::{self, Palette, BLACK};
+use effects;
+use flicker::Levels;
+use frontend::{self, Bell, Display, Input as _, Update, BLANK};
 use hardware::Gfx;
+use keypad;
+use osd::Osd;
 use piston_window::*;
-use std::sync::mpsc::channel;
-use std::thread;
-use std::time::Duration;
-
-pub fn run(rom: &str, shift_hack: bool, memory_hack: bool) {
-    let mut window: PistonWindow = WindowSettings::new("", [640, 320])
-        .build()
-        .unwrap();
-    
-    window.window.set_position([(1366 - 640) / 2, (768 - 320) / 2 - 100]);
-    
-    let (sender, receiver) = channel();
-    let (sender2, receiver2) = channel();
-
-    let hack = Hack { shift_hack, memory_hack };
-    let mut chip8 = Chip8::new(rom, hack);
-    let mut key_vx = 0_u8;
-
-    thread::spawn(move || {        
-        loop {
-            if let Ok(button_args) = receiver2.try_recv() {
-                chip8.hardware_key(button_args, key_vx);
-            }
-            chip8.cpu.tick();
-            for _ in 0..10 {
-                if !chip8.cpu.halted {
-                    chip8.debug();
-
-                    use cpu::Message::*;
-                    match chip8.step() {                          
-                        Draw(_, _, _) => {
-                            let _ = sender.send(chip8.hardware.gfx);
+use recorder::Recorder;
+use screenshot;
+use session::{Command, Session, Speed};
+use settings::Settings;
+use std::fs;
+use video;
+use window::{self, Controls, Window};
+
+// The window size for a game at its scale
+pub fn size(settings: &Settings) -> [u32; 2] {
+    content(settings.orientation.screen(), settings.keypad).map(|x| x as u32 * settings.scale)
+}
+
+// Plays a game until the window closes, which returns true, or until Escape.
+// load makes the emulator and its settings for any ROM dropped on the window
+pub fn run(window: &mut Window, (chip8, mut settings): (Chip8, Settings), rom: Vec<u8>,
+           load: impl Fn(&[u8]) -> Result<(Chip8, Settings), String>) -> Result<bool, String> {
+    let mut palettes: Vec<Palette> = settings.palettes.iter().map(|x| x.1).collect();
+    let mut palette = settings.palette;
+    let mut show_keypad = settings.keypad;
+    let mut screen = settings.orientation.screen();
+    let mut session = Session::start(chip8, rom, &settings, Bell);
+    let mut osd = Osd::new(settings.status);
+    window.window.set_title(title(&settings));
+
+    // the texture is rebuilt only when the screen or the palette changed
+    let mut shown = Screen { gfx: BLANK.0, levels: BLANK.1, keys: BLANK.2, dirty: true };
+    let mut controls = Controls::default();
+    let mut cursor = [0.0, 0.0];
+    // the keypad keys held by the mouse, id -1, and by touches
+    let mut pointers: Vec<(i64, usize)> = vec![];
+    let mut switched = false;
+    // Shift+F12 saves the screen at 1x
+    let mut shift = false;
+    let mut speed = Speed::default();
+    // where the screen is drawn and how big its pixels are, updated with the window size
+    let mut layout = Layout::fit(window.size(), screen, show_keypad, settings.fractional);
+    let mut rendered_scale = 0;
+    let mut texture_context = window.window.create_texture_context();
+    let mut texture: Option<G2dTexture> = None;
+    // the messages and the status bar over the screen, and the size they were drawn at
+    let mut overlay: Option<G2dTexture> = None;
+    let mut overlay_size = [0, 0];
+    // F10 records what the screen shows until pressed again
+    let mut recorder: Option<Recorder> = None;
+
+    while let Some(event) = window.window.next() {
+        window.handle(&event);
+        let mut pointer = None;
+        match event {
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::Escape), state: ButtonState::Press, .. }), _) => {
+                stop(recorder.take(), session.stats().frames, &mut osd);
+                return Ok(false);
+            },
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::F1), state: ButtonState::Press, .. }), _) => {
+                show_keypad = !show_keypad;
+                osd.show(if show_keypad { "keypad shown" } else { "keypad hidden" });
+                // the window grows or shrinks by the keypad, keeping the size of the pixels
+                if !window.fullscreen {
+                    let [width, height] = content(screen, show_keypad).map(|x| (x * layout.scale).round() as u32);
+                    window.window.window.set_size([width, height]);
+                }
+                layout = Layout::fit(window.size(), screen, show_keypad, settings.fractional);
+            },
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::F2), state: ButtonState::Press, .. }), _) => {
+                palette = (palette + 1) % palettes.len();
+                osd.show(format!("palette {}", settings.palettes[palette].0));
+                shown.dirty = true;
+            },
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::F3), state: ButtonState::Press, .. }), _) => {
+                osd.toggle();
+            },
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::F4), state: ButtonState::Press, .. }), _) => {
+                switched = !switched;
+                osd.show(if look(&settings, 1, switched).effects.is_empty() { "effects off" } else { "effects on" });
+                shown.dirty = true;
+            },
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::F5), state: ButtonState::Press, .. }), _) => {
+                speed.paused = !speed.paused;
+                osd.show(if speed.paused { "paused" } else { "resumed" });
+                session.send(Command::Speed(speed));
+            },
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::F6), state: ButtonState::Press, .. }), _) if speed.paused => {
+                session.send(Command::Step);
+            },
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::F7), state: ButtonState::Press, .. }), _) => {
+                speed.slow = !speed.slow;
+                osd.show(if speed.slow { "slow motion" } else { "normal speed" });
+                session.send(Command::Speed(speed));
+            },
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::Tab), state, .. }), _) => {
+                speed.fast = state == ButtonState::Press;
+                if speed.fast && settings.turbo == 0 {
+                    osd.show("fast forward");
+                } else if speed.fast {
+                    osd.show(format!("speed {}x", settings.turbo));
+                }
+                session.send(Command::Speed(speed));
+            },
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::F8), state: ButtonState::Press, .. }), _) => {
+                osd.show("reset");
+                session.send(Command::Reset);
+            },
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::F9), state: ButtonState::Press, .. }), _) => {
+                osd.show("hard reset");
+                session.send(Command::HardReset);
+            },
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::F10), state: ButtonState::Press, .. }), _) => {
+                let frame = session.stats().frames;
+                if recorder.is_some() {
+                    stop(recorder.take(), frame, &mut osd);
+                } else {
+                    let name = settings.title.as_deref().unwrap_or("chip8");
+                    let options = look(&settings, rendered_scale.max(1), switched);
+                    let started = screenshot::name(&settings.screenshots, name, "gif")
+                        .and_then(|path| Recorder::start(&path, palettes[palette], options, frame));
+                    match started {
+                        Ok(mut started) => {
+                            started.show(&(shown.gfx, shown.levels, shown.keys, frame))?;
+                            recorder = Some(started);
+                            osd.show("recording");
                         },
-                        GetKey(bits2) => key_vx = bits2,
-                        _ => ()
+                        Err(error) => {
+                            osd.show(error.clone());
+                            eprintln!("{}", error);
+                        }
                     }
-                }     
-            }                  
-            thread::sleep(Duration::new(0, 8333333));
+                }
+            },
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::F12), state: ButtonState::Press, .. }), _) => {
+                let scale = if shift { 1 } else { rendered_scale.max(1) };
+                let name = settings.title.as_deref().unwrap_or("chip8");
+                let saved = screenshot::name(&settings.screenshots, name, settings.screenshot_format.extension()).and_then(|path| {
+                    let frame = || video::render(&shown.gfx, &shown.levels, palettes[palette], &look(&settings, scale, switched));
+                    screenshot::save(&path, &shown.gfx, frame).map(|_| path)
+                });
+                match saved {
+                    Ok(path) => osd.show(format!("saved {}", path.display())),
+                    Err(error) => {
+                        osd.show(error.clone());
+                        eprintln!("{}", error);
+                    }
+                }
+            },
+            Event::Input(Input::FileDrag(FileDrag::Drop(ref path)), _) => {
+                let loaded = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))
+                    .and_then(|rom| load(&rom).map(|game| (rom, game)));
+                match loaded {
+                    Ok((rom, (chip8, new))) => {
+                        // the old session stops before the new one starts, and so does its recording
+                        stop(recorder.take(), session.stats().frames, &mut osd);
+                        drop(session);
+                        session = Session::start(chip8, rom, &new, Bell);
+                        settings = new;
+                        palettes = settings.palettes.iter().map(|x| x.1).collect();
+                        palette = settings.palette;
+                        screen = settings.orientation.screen();
+                        layout = Layout::fit(window.size(), screen, show_keypad, settings.fractional);
+                        speed = Speed::default();
+                        pointers.clear();
+                        shown.dirty = true;
+                        window.window.set_title(title(&settings));
+                        osd.show(format!("loaded {}", settings.title.as_deref().unwrap_or("a new game")));
+                    },
+                    Err(error) => {
+                        osd.show(error.clone());
+                        eprintln!("{}", error);
+                    }
+                }
+            },
+            Event::Input(Input::Resize(_), _) => {
+                layout = Layout::fit(window.size(), screen, show_keypad, settings.fractional);
+            },
+            Event::Input(Input::Move(Motion::MouseCursor(position)), _) => cursor = position,
+            Event::Input(Input::Button(ButtonArgs { button: Button::Mouse(MouseButton::Left), state, .. }), _) => {
+                pointer = Some((-1, cursor, state == ButtonState::Press));
+            },
+            Event::Input(Input::Move(Motion::Touch(touch)), _) => {
+                let size = window.size();
+                let position = [touch.position_3d[0] * size.width, touch.position_3d[1] * size.height];
+                match touch.touch {
+                    Touch::Start => pointer = Some((touch.id, position, true)),
+                    Touch::End | Touch::Cancel => pointer = Some((touch.id, position, false)),
+                    Touch::Move => ()
+                }
+            },
+            Event::Input(ref input @ Input::Button(_), _) => {
+                if let Input::Button(ButtonArgs { button: Button::Keyboard(Key::LShift), state, .. })
+                     | Input::Button(ButtonArgs { button: Button::Keyboard(Key::RShift), state, .. }) = *input {
+                    shift = state == ButtonState::Press;
+                }
+                controls.feed(input);
+            },
+            _ => ()
         }
-    });
 
-    let mut gfx_cache: Gfx = [[0; 64]; 32];
+        if let Some((id, [x, y], pressed)) = pointer {
+            if pressed {
+                let [left, top] = layout.keypad;
+                if let Some(number) = keypad::cell(x - left, y - top, layout.scale).filter(|_| show_keypad) {
+                    pointers.push((id, number));
+                    session.send(Command::Event(frontend::Event::Keypad(number, true)));
+                }
+            } else if let Some(index) = pointers.iter().position(|pointer| pointer.0 == id) {
+                let (_, number) = pointers.remove(index);
+                if !pointers.iter().any(|pointer| pointer.1 == number) {
+                    session.send(Command::Event(frontend::Event::Keypad(number, false)));
+                }
+            }
+        }
 
-    while let Some(event) = window.next() {
-        if let Some(button_args) = event.button_args() {
-            let _ = sender2.send(button_args);
+        for event in controls.poll() {
+            session.send(Command::Event(event));
+        }
+        #[cfg(feature = "gamepad")]
+        for event in window.gamepads.poll() {
+            session.send(Command::Event(event));
         }
-        if let Ok(gfx) = receiver.try_recv() {
-            gfx_cache = gfx;
+
+        if let Some(update) = session.update() {
+            recorder.show(&update)?;
+            shown.show(&update)?;
         }
-        window.draw_2d(&event, |context, graphics, _| {
-            render(&context, graphics, gfx_cache);
+        // the frame is made at the whole scale under the window's, the texture covers the rest
+        let scale = (layout.scale.floor() as u32).clamp(1, video::MAX_SCALE);
+        if (shown.dirty || scale != rendered_scale) && event.render_args().is_some() {
+            let frame = video::render(&shown.gfx, &shown.levels, palettes[palette], &look(&settings, scale, switched));
+            texture = Some(window::upload(&mut texture_context, texture, &frame)?);
+            shown.dirty = false;
+            rendered_scale = scale;
+        }
+        // the text is a fifth of a CHIP-8 pixel big, so the same amount fits at any size
+        let text_zoom = (layout.scale / 5.0).floor().max(1.0);
+        if event.render_args().is_some() {
+            osd.frame(session.stats());
+            let size = [screen.0, screen.1].map(|x| (x as f64 * layout.scale / text_zoom) as u32);
+            if osd.visible() && (osd.changed() || size != overlay_size) {
+                let name = settings.title.as_deref().unwrap_or("unknown game");
+                let frame = osd.render(size[0], size[1], name, palettes[palette]);
+                overlay = Some(window::upload(&mut texture_context, overlay, &frame)?);
+                overlay_size = size;
+            }
+        }
+        window.window.draw_2d(&event, |context, graphics, device| {
+            texture_context.encoder.flush(device);
+            // black bars around the screen and the keypad
+            clear(BLACK, graphics);
+            let [x, y] = layout.origin;
+            let [width, height] = content(screen, show_keypad).map(|size| size * layout.scale);
+            rectangle(data::linear(palettes[palette][0]), [x, y, width, height], context.transform, graphics);
+            if let Some(ref texture) = texture {
+                let zoom = layout.scale / rendered_scale as f64;
+                image(texture, context.transform.trans(x, y).zoom(zoom), graphics);
+            }
+            if let Some(overlay) = overlay.as_ref().filter(|_| osd.visible()) {
+                image(overlay, context.transform.trans(x, y).zoom(text_zoom), graphics);
+            }
+            if show_keypad {
+                keypad::render(&context, graphics, &shown.keys, palettes[palette], layout.keypad, layout.scale);
+            }
+            let corner = [x + screen.0 as f64 * layout.scale, y];
+            status(&context, graphics, speed, recorder.is_some(), corner, layout.scale, data::linear(palettes[palette][1]));
         });
     }
+
+    stop(recorder.take(), session.stats().frames, &mut osd);
+    Ok(true)
 }
 
-fn render(context: &Context, graphics: &mut impl Graphics, gfx2: Gfx) {
-    clear(data::BLUE, graphics);
-    for y in 0..32 {
-        for x in 0..64 {            
-            if gfx2[y][x] == 1 {
-                let rect = [x as f64 * 10.0, y as f64 * 10.0, 9.0, 9.0];
-                rectangle(data::GRAY, rect, context.transform, graphics);
-            }
-        }
+// The piston display, the screen the window draws at its next render
+struct Screen {
+    gfx: Gfx,
+    levels: Levels,
+    keys: [bool; 16],
+    dirty: bool
+}
+
+impl Display for Screen {
+    fn show(&mut self, &(gfx, levels, keys, _): &Update) -> Result<(), String> {
+        self.gfx = gfx;
+        self.levels = levels;
+        self.keys = keys;
+        self.dirty = true;
+        Ok(())
+    }
+}
+
+// The window title, the game's when the database knows it
+fn title(settings: &Settings) -> String {
+    match settings.title {
+        Some(ref title) => format!("{} - chip-8", title),
+        None => "chip-8".to_string()
+    }
+}
+
+// The screen, turned as it is shown, and the keypad right of it, in CHIP-8 pixels
+fn content(screen: (u32, u32), show_keypad: bool) -> [f64; 2] {
+    let (width, height) = screen;
+    if show_keypad {
+        [(width + keypad::WIDTH) as f64, height.max(keypad::WIDTH) as f64]
+    } else {
+        [width as f64, height as f64]
+    }
+}
+
+struct Layout {
+    // window pixels per CHIP-8 pixel
+    scale: f64,
+    // the top left corners of the screen and the keypad
+    origin: [f64; 2],
+    keypad: [f64; 2]
+}
+
+impl Layout {
+    fn fit(window: Size, screen: (u32, u32), show_keypad: bool, fractional: bool) -> Layout {
+        let (scale, origin) = window::fit(window, content(screen, show_keypad), fractional);
+        Layout { scale, origin, keypad: [origin[0] + screen.0 as f64 * scale, origin[1]] }
+    }
+}
+
+// Ends the recording if there is one and tells where it went
+fn stop(recorder: Option<Recorder>, frame: u64, osd: &mut Osd) {
+    match recorder.map(|recorder| recorder.finish(frame)) {
+        Some(Ok(path)) => osd.show(format!("saved {}", path.display())),
+        Some(Err(error)) => {
+            osd.show(error.clone());
+            eprintln!("{}", error);
+        },
+        None => ()
+    }
+}
+
+// A sign in the top right corner of the screen for anything but normal speed, and a dot left of it while recording
+fn status(context: &Context, graphics: &mut impl Graphics, speed: Speed, recording: bool, [right, top]: [f64; 2], scale: f64, color: [f32; 4]) {
+    let color = [color[0], color[1], color[2], 0.8];
+    let size = 4.0 * scale;
+    let [x, y] = [right - size - scale, top + scale];
+    let triangle = |left: f64, width: f64| [[left, y], [left + width, y + size / 2.0], [left, y + size]];
+
+    if speed.paused {
+        // two bars
+        rectangle(color, [x, y, size / 3.0, size], context.transform, graphics);
+        rectangle(color, [x + size * 2.0 / 3.0, y, size / 3.0, size], context.transform, graphics);
+    } else if speed.fast {
+        // two arrows
+        polygon(color, &triangle(x, size / 2.0), context.transform, graphics);
+        polygon(color, &triangle(x + size / 2.0, size / 2.0), context.transform, graphics);
+    } else if speed.slow {
+        // a bar and an arrow
+        rectangle(color, [x, y, size / 4.0, size], context.transform, graphics);
+        polygon(color, &triangle(x + size / 2.0, size / 2.0), context.transform, graphics);
+    }
+    if recording {
+        ellipse(color, [x - size - scale, y, size, size], context.transform, graphics);
+    }
+}
+
+// F4 switches between the look of the game and plain pixels, or a CRT when it has no effects
+fn look(settings: &Settings, scale: u32, switched: bool) -> video::Options {
+    let options = settings.video(scale);
+    match (switched, options.effects.is_empty()) {
+        (false, _) => options,
+        (true, true) => video::Options { effects: effects::CRT.to_vec(), ..options },
+        (true, false) => video::Options { gap: 0, grid: false, effects: vec![], ..options }
     }
 }
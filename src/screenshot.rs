@@ -0,0 +1,86 @@
+use hardware::Gfx;
+use image::{DynamicImage, RgbaImage};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+#[cfg(feature = "piston")]
+use std::path::PathBuf;
+#[cfg(feature = "piston")]
+use std::time::{SystemTime, UNIX_EPOCH};
+use video::Frame;
+
+// What F12 saves, PBM is the screen itself without palette or scale
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Png,
+    Ppm,
+    Pbm
+}
+
+#[cfg(feature = "piston")]
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Format::Png => "png",
+            Format::Ppm => "ppm",
+            Format::Pbm => "pbm"
+        }
+    }
+}
+
+// The format goes by the extension: PBM writes the CHIP-8 screen, 1 for every lit pixel,
+// anything else is the frame the palette and the scale made, in whatever format the image crate knows
+pub fn save(path: &Path, gfx: &Gfx, frame: impl FnOnce() -> Frame) -> Result<(), String> {
+    let pbm = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("pbm"));
+    if pbm {
+        return fs::write(path, bitmap(gfx)).map_err(|e| format!("{}: {}", path.display(), e));
+    }
+    let frame = frame();
+    let image = RgbaImage::from_raw(frame.width, frame.height, frame.pixels)
+        .ok_or_else(|| format!("{}: the frame does not match its size", path.display()))?;
+    DynamicImage::ImageRgba8(image).to_rgb8().save(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Plain PBM, a text file with a line per row that diffs well, the digits need no spaces between them
+pub fn bitmap(gfx: &Gfx) -> String {
+    let mut text = format!("P1\n{} {}\n", gfx[0].len(), gfx.len());
+    for row in gfx.iter() {
+        text.extend(row.iter().map(|&pixel| if pixel > 0 { '1' } else { '0' }));
+        text.push('\n');
+    }
+    text
+}
+
+// A name in the folder that is not taken yet, like pong-20261019-153012.png for screenshots and recordings with the time in UTC,
+// the folder is made if it is not there
+#[cfg(feature = "piston")]
+pub fn name(folder: &Path, title: &str, extension: &str) -> Result<PathBuf, String> {
+    fs::create_dir_all(folder).map_err(|e| format!("{}: {}", folder.display(), e))?;
+    let title: String = title.to_lowercase().split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty()).collect::<Vec<_>>().join("-");
+    let stem = format!("{}-{}", if title.is_empty() { "chip8" } else { &title }, timestamp());
+
+    (1..).map(|i| match i {
+        1 => folder.join(format!("{}.{}", stem, extension)),
+        _ => folder.join(format!("{}-{}.{}", stem, i, extension))
+    }).find(|path| !path.exists()).ok_or_else(|| "no free name for a screenshot".to_string())
+}
+
+// YYYYMMDD-HHMMSS in UTC
+#[cfg(feature = "piston")]
+fn timestamp() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0);
+    let (days, time) = ((seconds / 86400) as i64, seconds % 86400);
+    // from days since 1970 to the civil date, counting in eras of 400 years from March 2000
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
@@ -0,0 +1,346 @@
+use chip8::Chip8;
+use cpu::Fault;
+use flicker::{Filter, Flicker};
+use frontend::{Audio, Event, Update, BLANK};
+use settings::Settings;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// What the frontend sends to the emulator thread
+pub enum Command {
+    Event(Event),
+    Speed(Speed),
+    // runs one frame while paused
+    Step,
+    // back to 0x200 with the registers cleared
+    Reset,
+    // a reset with the ROM loaded again, undoing whatever it wrote to memory
+    HardReset
+}
+
+// F5 pauses, Tab held fast forwards and F7 slows down to a quarter
+#[derive(Clone, Copy, Default)]
+pub struct Speed {
+    pub paused: bool,
+    pub fast: bool,
+    pub slow: bool
+}
+
+impl Speed {
+    // How long a frame lasts, turbo frames fit in one when fast forwarding, 0 runs them as fast as it can
+    fn period(&self, turbo: u32) -> Duration {
+        let frame = Duration::new(0, 16666667);
+        match *self {
+            Speed { fast: true, .. } if turbo == 0 => Duration::new(0, 0),
+            Speed { fast: true, .. } => frame / turbo,
+            Speed { slow: true, .. } => frame * 4,
+            _ => frame
+        }
+    }
+}
+
+// The CPU at the end of a frame, for the terminal's side pane
+#[cfg(feature = "terminal")]
+#[derive(Clone, Copy, Default)]
+pub struct Registers {
+    pub pc: u16,
+    pub i: u16,
+    pub v: [u8; 16],
+    pub delay: u8,
+    pub sound: u8,
+    // how deep the stack is and the address on top of it
+    pub stack: (usize, Option<u16>),
+    // the next instruction
+    pub opcode: (u8, u8),
+    // FX0A waiting for a key
+    pub halted: bool
+}
+
+// How the game is doing, for the status bar and the messages
+#[derive(Clone, Copy, Default)]
+pub struct Stats {
+    // every frame and instruction run since the session started, pauses do not count
+    pub frames: u64,
+    pub instructions: u64,
+    pub sounding: bool,
+    // the address and opcode of an instruction the CPU does not know, once
+    pub unknown: Option<(u16, u16)>,
+    // what stopped the CPU, until a reset
+    pub fault: Option<Fault>,
+    // the emulator thread died, the game is over until another one starts
+    pub crashed: bool,
+    #[cfg(feature = "terminal")]
+    pub registers: Registers
+}
+
+// What the two threads share, each one holds the lock only to copy in or out
+struct Shared {
+    // the last whole frame, the emulator works on its own copy until the frame ends
+    front: Update,
+    // whether the frontend has not taken the front frame yet
+    fresh: bool,
+    // what the frontend sent since the last frame, the emulator takes it all at the start of a frame
+    commands: Vec<Command>,
+    stats: Stats,
+    stop: bool
+}
+
+// One game running on its own thread, which stops when the session is dropped
+pub struct Session {
+    shared: Arc<Mutex<Shared>>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl Session {
+    // The audio hears the buzzer when the settings let it
+    pub fn start(mut chip8: Chip8, rom: Vec<u8>, settings: &Settings, mut audio: impl Audio + Send + 'static) -> Session {
+        let ipf = settings.ipf;
+        let turbo = settings.turbo;
+        let audible = settings.audio;
+        let flicker = settings.flicker;
+        let mut filter = Filter::new(flicker);
+        let filtered = flicker != Flicker::Off;
+        let shared = Arc::new(Mutex::new(Shared {
+            front: BLANK,
+            fresh: false,
+            commands: vec![],
+            stats: Stats::default(),
+            stop: false
+        }));
+        let emulator = shared.clone();
+
+        let thread = thread::spawn(move || {
+            let mut speed = Speed::default();
+            let mut next = Instant::now();
+            let mut frames = 0;
+            loop {
+                let keys = chip8.hardware.keys;
+                let commands = match emulator.lock() {
+                    Ok(ref mut shared) if !shared.stop => mem::take(&mut shared.commands),
+                    // stopped, or the frontend panicked holding the lock
+                    _ => return
+                };
+                let mut steps = 0;
+                let mut reset = false;
+                for command in commands {
+                    match command {
+                        Command::Event(event) => chip8.event(event),
+                        Command::Speed(new) => speed = new,
+                        Command::Step => steps += 1,
+                        Command::Reset => {
+                            chip8.reset();
+                            reset = true;
+                        },
+                        Command::HardReset => {
+                            chip8.reload(&rom);
+                            reset = true;
+                        }
+                    }
+                }
+                if reset {
+                    filter = Filter::new(flicker);
+                }
+
+                let running = !speed.paused || steps > 0;
+                let sounding = chip8.cpu.sound_timer > 0;
+                let drawn = running && chip8.frame(ipf);
+                if running {
+                    filter.push(&chip8.hardware.gfx);
+                    frames += 1;
+                }
+                // blended and fading pixels change on frames that draw nothing
+                if reset || drawn || (running && filtered) || keys != chip8.hardware.keys {
+                    if let Ok(mut shared) = emulator.lock() {
+                        shared.front = (filter.colors, filter.levels, chip8.hardware.keys, frames);
+                        shared.fresh = true;
+                    }
+                }
+                if let Ok(mut shared) = emulator.lock() {
+                    shared.stats.frames = frames;
+                    shared.stats.instructions = chip8.instructions;
+                    shared.stats.sounding = chip8.cpu.sound_timer > 0;
+                    shared.stats.fault = chip8.cpu.fault;
+                    #[cfg(feature = "terminal")]
+                    {
+                        shared.stats.registers = registers(&chip8);
+                    }
+                    if let Some(unknown) = chip8.unknown.take() {
+                        shared.stats.unknown = Some(unknown);
+                    }
+                }
+                if audible && sounding != (chip8.cpu.sound_timer > 0) {
+                    audio.buzz(!sounding);
+                }
+
+                // frames keep to the clock, a late one moves the clock instead of rushing the next ones
+                next += speed.period(turbo);
+                let now = Instant::now();
+                if next > now {
+                    thread::sleep(next - now);
+                } else {
+                    next = now;
+                }
+            }
+        });
+
+        Session { shared, thread: Some(thread) }
+    }
+
+    // Reaches the game at the start of its next frame
+    pub fn send(&self, command: Command) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.commands.push(command);
+        }
+    }
+
+    // The last frame, once
+    pub fn update(&self) -> Option<Update> {
+        let mut shared = self.shared.lock().ok()?;
+        if !shared.fresh {
+            return None;
+        }
+        shared.fresh = false;
+        Some(shared.front)
+    }
+
+    // The unknown instruction is taken with them. A thread that ended without being stopped
+    // panicked, and a lock it poisoned still has the last stats
+    pub fn stats(&self) -> Stats {
+        let crashed = self.thread.as_ref().is_some_and(|thread| thread.is_finished());
+        let mut shared = self.shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let stats = Stats { crashed, ..shared.stats };
+        shared.stats.unknown = None;
+        stats
+    }
+}
+
+#[cfg(feature = "terminal")]
+fn registers(chip8: &Chip8) -> Registers {
+    let cpu = &chip8.cpu;
+    Registers {
+        pc: cpu.pc,
+        i: cpu.i,
+        v: cpu.v,
+        delay: cpu.delay_timer,
+        sound: cpu.sound_timer,
+        stack: (cpu.stack.len(), cpu.stack.last().cloned()),
+        opcode: cpu.opcode(),
+        halted: cpu.halted
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // the thread finishes the frame it is on
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.stop = true;
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Command, Session, Speed, Stats};
+    use chip8::Chip8;
+    use config::Config;
+    use cpu::Fault;
+    use frontend::Event;
+    use frontend::test::Buzzes;
+    use settings::{Overrides, Settings};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // LDB 0, 30; SST 0; JP 204, a beep of half a second
+    const BEEP: [u8; 6] = [0x60, 0x1E, 0xF0, 0x18, 0x12, 0x04];
+
+    fn start(audio: bool, buzzes: &Buzzes) -> Session {
+        let mut settings = Settings::resolve(&BEEP, &Config::default(), &Overrides::default());
+        settings.audio = audio;
+        settings.turbo = 0;
+        let chip8 = Chip8::new(&BEEP, settings.hack());
+        let session = Session::start(chip8, BEEP.to_vec(), &settings, buzzes.clone());
+        session.send(Command::Speed(Speed { fast: true, ..Speed::default() }));
+        session
+    }
+
+    fn wait(session: &Session, frames: u64) {
+        let start = Instant::now();
+        while session.stats().frames < frames {
+            assert!(start.elapsed() < Duration::from_secs(10), "the session stalled");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn the_audio_hears_the_buzzer_start_and_stop() {
+        let buzzes = Buzzes::default();
+        let session = start(true, &buzzes);
+        wait(&session, 60);
+        drop(session);
+        assert_eq!(*buzzes.buzzes.lock().unwrap(), vec![true, false]);
+    }
+
+    #[test]
+    fn the_audio_is_not_told_when_the_settings_mute_it() {
+        let buzzes = Buzzes::default();
+        let session = start(false, &buzzes);
+        wait(&session, 60);
+        drop(session);
+        assert!(buzzes.buzzes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn events_reach_the_game_and_come_back_in_the_update() {
+        let session = start(false, &Buzzes::default());
+        session.send(Command::Event(Event::Keypad(7, true)));
+        let start = Instant::now();
+        loop {
+            assert!(start.elapsed() < Duration::from_secs(10), "the key never showed");
+            match session.update() {
+                Some(update) if update.2[7] => break,
+                _ => thread::sleep(Duration::from_millis(1))
+            }
+        }
+    }
+
+    fn run(rom: &[u8]) -> Session {
+        let settings = Settings::resolve(rom, &Config::default(), &Overrides::default());
+        Session::start(Chip8::new(rom, settings.hack()), rom.to_vec(), &settings, Buzzes::default())
+    }
+
+    fn wait_for(session: &Session, what: &str, done: impl Fn(&Stats) -> bool) -> Stats {
+        let start = Instant::now();
+        loop {
+            let stats = session.stats();
+            if done(&stats) {
+                return stats;
+            }
+            assert!(start.elapsed() < Duration::from_secs(10), "{} never showed", what);
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn a_fault_stops_the_game_and_shows_in_the_stats() {
+        // RET
+        let session = run(&[0x00, 0xEE]);
+        let stats = wait_for(&session, "the fault", |stats| stats.fault.is_some());
+        assert_eq!(stats.fault, Some(Fault::StackUnderflow(0x200)));
+        assert_eq!(stats.instructions, 1);
+        assert!(!stats.crashed);
+    }
+
+    #[test]
+    fn reading_past_the_end_of_memory_keeps_the_game_running() {
+        // LDI FFF; DRW 0, 0, 2; JP 200
+        let session = run(&[0xAF, 0xFF, 0xD0, 0x02, 0x12, 0x00]);
+        let stats = wait_for(&session, "a few frames", |stats| stats.instructions > 100);
+        assert_eq!(stats.fault, None);
+        assert!(!stats.crashed);
+    }
+}
@@ -0,0 +1,203 @@
+use config::{Config, Profile};
+use cpu::Hack;
+use data::{self, Palette};
+use database::{self, Game, Quirks};
+use effects::Effect;
+use flicker::Flicker;
+use hardware::Control;
+use orientation::{Flip, Orientation};
+use screenshot::Format;
+use std::path::PathBuf;
+use upscale::Upscaler;
+use video::Options;
+
+// Settings given on the command line, they win over the config and the database
+#[derive(Default)]
+pub struct Overrides {
+    pub quirks: Option<Quirks>,
+    pub ipf: Option<u32>,
+    pub scale: Option<u32>,
+    pub layout: Option<String>,
+    pub keypad: Option<bool>,
+    pub palette: Option<String>,
+    pub flicker: Option<Flicker>,
+    pub gap: Option<u32>,
+    pub grid: Option<bool>,
+    pub upscaler: Option<Upscaler>,
+    pub effects: Option<Vec<Effect>>,
+    pub fractional: Option<bool>,
+    pub rotation: Option<u16>,
+    pub flip: Option<Flip>,
+    pub turbo: Option<u32>,
+    pub status: Option<bool>
+}
+
+// The same with every frontend, the ones not built in leave their settings unread
+#[cfg_attr(not(feature = "piston"), allow(dead_code))]
+pub struct Settings {
+    // the title in the database, if the game is there
+    pub title: Option<String>,
+    pub quirks: Quirks,
+    pub ipf: u32,
+    pub scale: u32,
+    pub bindings: Vec<(Control, usize)>,
+    // every palette there is to cycle through and the one in use
+    pub palettes: Vec<(String, Palette)>,
+    pub palette: usize,
+    pub audio: bool,
+    pub trace: Option<String>,
+    // the clickable keypad next to the screen
+    pub keypad: bool,
+    pub flicker: Flicker,
+    // a tenth of the scale when not set
+    pub gap: Option<u32>,
+    pub grid: bool,
+    pub upscaler: Upscaler,
+    pub effects: Vec<Effect>,
+    // scales the window by any factor instead of whole ones
+    pub fractional: bool,
+    pub orientation: Orientation,
+    // how many times faster Tab runs the game, 0 for as fast as it can
+    pub turbo: u32,
+    // the status bar under the screen
+    pub status: bool,
+    // where and how F12 saves the screen
+    pub screenshots: PathBuf,
+    pub screenshot_format: Format
+}
+
+impl Settings {
+    pub fn resolve(rom: &[u8], config: &Config, overrides: &Overrides) -> Settings {
+        Settings::from_game(&database::hash(rom), database::lookup(rom).as_ref(), config, overrides)
+    }
+
+    // The command line wins over the game's profile in the config,
+    // which wins over the database, which wins over the config's defaults
+    pub fn from_game(hash: &str, game: Option<&Game>, config: &Config, overrides: &Overrides) -> Settings {
+        let database = game.map(Profile::from);
+        let layers: Vec<&Profile> = config.game(hash, game.map(|game| game.title.as_str())).into_iter()
+            .chain(database.as_ref())
+            .chain(Some(&config.defaults))
+            .collect();
+
+        // each quirk comes from the first layer that sets it
+        let quirk = |field: fn(&Quirks) -> Option<bool>| overrides.quirks.as_ref().and_then(field)
+            .or_else(|| first(&layers, |layer| layer.quirks.as_ref().and_then(|x| x.quirks()).and_then(|x| field(&x))));
+        let quirks = Quirks { shift: quirk(|x| x.shift), load_store: quirk(|x| x.load_store) };
+
+        // the first binding of a key wins, so the keys of the layers go before the layout
+        let mut bindings = vec![];
+        for (name, number) in layers.iter().flat_map(|layer| layer.keys.iter().flatten()) {
+            if let Ok(number) = usize::from_str_radix(number, 16) {
+                if number < 16 {
+                    bindings.extend(data::controls_from_name(name).into_iter().map(|control| (control, number)));
+                }
+            }
+        }
+        // the layout and the gamepad follow the turned screen, the keys of the layers are already set for it
+        let orientation = Orientation {
+            rotation: overrides.rotation.or_else(|| first(&layers, |layer| layer.rotation)).unwrap_or(0),
+            flip: overrides.flip.or_else(|| first(&layers, |layer| layer.flip)).unwrap_or(Flip::Off)
+        };
+        let directions = orientation.directions();
+        let layout = overrides.layout.as_ref().and_then(|name| data::layout(name))
+            .or_else(|| first(&layers, |layer| layer.layout.as_ref().and_then(|name| data::layout(name))))
+            .or_else(|| data::layout("qwerty"));
+        bindings.extend(layout.into_iter().flatten().map(|(control, number)| (control, directions[number])));
+        #[cfg(feature = "gamepad")]
+        for &(name, number) in data::GAMEPAD.iter() {
+            bindings.extend(data::controls_from_name(name).into_iter().map(|control| (control, directions[number])));
+        }
+
+        let palettes = config.palettes();
+        // picked by name once the config's palettes are in, blue when none is picked
+        let find = |name: &str| palettes.iter().position(|palette| palette.0 == name);
+        let palette = overrides.palette.as_ref().and_then(|name| find(name))
+            .or_else(|| first(&layers, |layer| layer.palette.as_ref().and_then(|name| find(name))))
+            .or_else(|| find(data::PALETTES[0]))
+            .unwrap_or(0);
+
+        Settings {
+            title: game.map(|game| game.title.clone()),
+            quirks,
+            ipf: overrides.ipf.or_else(|| first(&layers, |layer| layer.ipf)).unwrap_or(20),
+            scale: overrides.scale.or_else(|| first(&layers, |layer| layer.scale)).unwrap_or(10),
+            bindings,
+            palettes,
+            palette,
+            audio: first(&layers, |layer| layer.audio.as_ref().and_then(|x| x.enabled)).unwrap_or(false),
+            trace: first(&layers, |layer| layer.trace.clone()),
+            keypad: overrides.keypad.or_else(|| first(&layers, |layer| layer.keypad)).unwrap_or(false),
+            flicker: overrides.flicker.or_else(|| first(&layers, |layer| layer.flicker)).unwrap_or(Flicker::Off),
+            gap: overrides.gap.or_else(|| first(&layers, |layer| layer.gap)),
+            grid: overrides.grid.or_else(|| first(&layers, |layer| layer.grid)).unwrap_or(false),
+            upscaler: overrides.upscaler.or_else(|| first(&layers, |layer| layer.upscaler)).unwrap_or(Upscaler::Nearest),
+            effects: overrides.effects.clone().or_else(|| first(&layers, |layer| layer.effects.clone())).unwrap_or_default(),
+            fractional: overrides.fractional.or_else(|| first(&layers, |layer| layer.fractional)).unwrap_or(false),
+            orientation,
+            turbo: overrides.turbo.or_else(|| first(&layers, |layer| layer.turbo)).unwrap_or(4),
+            status: overrides.status.or_else(|| first(&layers, |layer| layer.status)).unwrap_or(false),
+            screenshots: first(&layers, |layer| layer.screenshots.as_ref().and_then(|x| x.folder.clone()))
+                .unwrap_or_else(|| "screenshots".to_string()).into(),
+            screenshot_format: first(&layers, |layer| layer.screenshots.as_ref().and_then(|x| x.format)).unwrap_or(Format::Png)
+        }
+    }
+
+    pub fn colors(&self) -> Palette {
+        self.palettes[self.palette].1
+    }
+
+    // the other upscalers blend pixels into each other, so only square pixels get a gap by default
+    pub fn video(&self, scale: u32) -> Options {
+        let gap = if self.upscaler == Upscaler::Nearest { scale / 10 } else { 0 };
+        Options { scale, gap: self.gap.unwrap_or(gap), grid: self.grid, upscaler: self.upscaler,
+                  effects: self.effects.clone(), orientation: self.orientation }
+    }
+
+    pub fn hack(&self) -> Hack {
+        Hack {
+            shift_hack: self.quirks.shift.unwrap_or(false),
+            memory_hack: self.quirks.load_store.unwrap_or(false)
+        }
+    }
+}
+
+fn first<T>(layers: &[&Profile], field: impl Fn(&Profile) -> Option<T>) -> Option<T> {
+    layers.iter().filter_map(|layer| field(layer)).next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Overrides, Settings};
+    use config::Config;
+    use database::{Game, Quirks};
+    use serde_json;
+
+    fn game(quirks: &str) -> Game {
+        serde_json::from_str(&format!(r#"{{ "title": "Pong", "quirks": {}, "description": "" }}"#, quirks)).unwrap()
+    }
+
+    fn quirks(settings: &Settings) -> (bool, bool) {
+        let hack = settings.hack();
+        (hack.shift_hack, hack.memory_hack)
+    }
+
+    #[test]
+    fn each_quirk_comes_from_the_first_layer_that_sets_it() {
+        let config: Config = serde_json::from_str(r#"{ "games": { "pong": { "quirks": { "shift": true } } } }"#).unwrap();
+        let settings = Settings::from_game("", Some(&game(r#"{ "loadStore": true }"#)), &config, &Overrides::default());
+        assert_eq!(quirks(&settings), (true, true));
+
+        let config: Config = serde_json::from_str(r#"{ "quirks": "schip" }"#).unwrap();
+        let settings = Settings::from_game("", Some(&game(r#"{ "shift": false }"#)), &config, &Overrides::default());
+        assert_eq!(quirks(&settings), (false, true));
+    }
+
+    #[test]
+    fn the_command_line_quirks_win() {
+        let config: Config = serde_json::from_str(r#"{ "quirks": { "shift": true } }"#).unwrap();
+        let overrides = Overrides { quirks: Quirks::profile("none"), ..Overrides::default() };
+        let settings = Settings::from_game("", Some(&game(r#"{ "loadStore": true }"#)), &config, &overrides);
+        assert_eq!(quirks(&settings), (false, false));
+    }
+}
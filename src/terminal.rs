@@ -0,0 +1,369 @@
+use chip8::Chip8;
+use data::Palette;
+use dissasembler::decode;
+use frontend::{Bell, Display, Event, Input, Update, BLANK};
+#[cfg(feature = "gamepad")]
+use gamepad::Gamepads;
+use hardware::Control;
+use key::Key;
+use session::{Command, Session, Speed, Stats};
+use settings::Settings;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use upscale::Upscaler;
+use video::{self, Frame, Options};
+
+// Terminals tell when a key goes down and when it repeats, never when it goes up,
+// so a key lets go this long after the last time it came. Longer than the wait
+// before the first repeat, which is 250 to 600 ms on most systems
+const HELD: Duration = Duration::from_millis(600);
+
+// Plays a game in the terminal until Escape or Ctrl+C, in colored half blocks, two pixels a character,
+// or in braille, eight pixels a character in two colors. The registers show on the right
+pub fn run((chip8, settings): (Chip8, Settings), rom: Vec<u8>, braille: bool) -> Result<(), String> {
+    // before the screen is taken, so why there are none can be read
+    #[cfg(feature = "gamepad")]
+    let mut gamepads = Gamepads::open();
+    // dropped last, it gives the terminal back as it was even on a panic
+    let _raw = raw::Raw::enter()?;
+
+    // the reader blocks on the input, so it has its own thread that stops with the program
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 64];
+        let stdin = io::stdin();
+        while let Ok(count) = stdin.lock().read(&mut buffer) {
+            if count == 0 || sender.send(buffer[..count].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let palette = settings.colors();
+    let options = Options { scale: 1, gap: 0, grid: false, upscaler: Upscaler::Nearest, effects: vec![], ..settings.video(1) };
+    let session = Session::start(chip8, rom, &settings, Bell);
+    let mut keyboard = Keyboard { receiver, held: HashMap::new(), pending: vec![] };
+    let mut screen = Screen { palette, options, braille, lines: vec![], keys: [false; 16] };
+    screen.show(&BLANK)?;
+    let mut speed = Speed::default();
+    // the lines on the terminal now
+    let mut shown: Vec<String> = vec![];
+
+    loop {
+        for event in keyboard.poll() {
+            match event {
+                Event::Control(Control::Key(Key::Escape), true) => return Ok(()),
+                Event::Control(Control::Key(Key::F5), true) => {
+                    speed.paused = !speed.paused;
+                    session.send(Command::Speed(speed));
+                },
+                Event::Control(Control::Key(Key::F6), true) if speed.paused => session.send(Command::Step),
+                Event::Control(Control::Key(Key::F8), true) => session.send(Command::Reset),
+                Event::Control(Control::Key(Key::F9), true) => session.send(Command::HardReset),
+                event => session.send(Command::Event(event))
+            }
+        }
+        #[cfg(feature = "gamepad")]
+        for event in gamepads.poll() {
+            session.send(Command::Event(event));
+        }
+
+        if let Some(update) = session.update() {
+            screen.show(&update)?;
+        }
+        let stats = session.stats();
+        let pane = pane(&stats, &screen.keys, speed);
+        // only the lines that changed go out, a terminal is slow to draw
+        let lines: Vec<String> = (0..screen.lines.len().max(pane.len())).map(|row| {
+            let left = screen.lines.get(row).map_or("", String::as_str);
+            let right = pane.get(row).map_or("", String::as_str);
+            format!("{}\x1b[0m  {}\x1b[K", left, right)
+        }).collect();
+        let mut out = String::new();
+        for (row, line) in lines.iter().enumerate() {
+            if shown.get(row) != Some(line) {
+                out += &format!("\x1b[{};1H{}", row + 1, line);
+            }
+        }
+        if !out.is_empty() {
+            let mut stdout = io::stdout();
+            stdout.write_all(out.as_bytes()).and_then(|_| stdout.flush()).map_err(|e| e.to_string())?;
+            shown = lines;
+        }
+
+        thread::sleep(Duration::from_millis(16));
+    }
+}
+
+// The terminal input, the keys the reader thread got, each held until a while after it last came
+struct Keyboard {
+    receiver: Receiver<Vec<u8>>,
+    held: HashMap<Key, Instant>,
+    // the start of an escape sequence the rest of which has not come yet
+    pending: Vec<u8>
+}
+
+impl Input for Keyboard {
+    fn poll(&mut self) -> Vec<Event> {
+        let now = Instant::now();
+        let mut events = vec![];
+        let mut received = false;
+        for bytes in self.receiver.try_iter() {
+            self.pending.extend(bytes);
+            received = true;
+        }
+        // what is still unfinished a frame later is all there is, a lone ESC is Escape
+        let (keys, used) = parse(&self.pending, !received);
+        self.pending.drain(..used);
+        for key in keys {
+            if key != Key::Unknown && self.held.insert(key, now).is_none() {
+                events.push(Event::Control(Control::Key(key), true));
+            }
+        }
+        let released: Vec<Key> = self.held.iter().filter(|&(_, &time)| now - time >= HELD).map(|(&key, _)| key).collect();
+        for key in released {
+            self.held.remove(&key);
+            events.push(Event::Control(Control::Key(key), false));
+        }
+        events
+    }
+}
+
+// The terminal display, the screen as lines of characters and the keys held for the pane
+struct Screen {
+    palette: Palette,
+    options: Options,
+    braille: bool,
+    lines: Vec<String>,
+    keys: [bool; 16]
+}
+
+impl Display for Screen {
+    fn show(&mut self, &(ref colors, ref levels, keys, _): &Update) -> Result<(), String> {
+        let frame = video::render(colors, levels, self.palette, &self.options);
+        self.lines = if self.braille { dots(&frame, self.palette[0], self.palette[1]) } else { blocks(&frame) };
+        self.keys = keys;
+        Ok(())
+    }
+}
+
+// The keys in what the terminal sent: letters, digits and the like, arrows, function keys,
+// Enter and Escape. Ctrl+C counts as Escape, since raw mode turns off the signal.
+// Unless the bytes are complete, an escape sequence cut at the end is left for the next read,
+// with how many bytes were used
+fn parse(bytes: &[u8], complete: bool) -> (Vec<Key>, usize) {
+    let mut keys = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let (key, length) = match bytes[i..] {
+            [0x1b] | [0x1b, b'['] | [0x1b, b'O'] if !complete => break,
+            [0x1b] | [0x03, ..] => (Key::Escape, 1),
+            // CSI, ESC [ then parameters up to a final byte
+            [0x1b, b'[', ref rest @ ..] => match rest.iter().position(|&x| (0x40..=0x7e).contains(&x)) {
+                Some(end) => (sequence(&rest[..end], rest[end]), end + 3),
+                None if !complete => break,
+                None => (Key::Unknown, bytes.len() - i)
+            },
+            // SS3, ESC O then one letter
+            [0x1b, b'O', last, ..] => (sequence(&[], last), 3),
+            // Alt with a key
+            [0x1b, _, ..] => (Key::Unknown, 2),
+            [b'\r', ..] | [b'\n', ..] => (Key::Return, 1),
+            [0x7f, ..] => (Key::Backspace, 1),
+            [c, ..] if c == b'\t' || c == b' ' || c.is_ascii_graphic() => (Key::from_char(c.to_ascii_lowercase() as char), 1),
+            _ => (Key::Unknown, 1)
+        };
+        keys.push(key);
+        i += length;
+    }
+    (keys, i)
+}
+
+// Arrows end in A to D, F1 to F4 in P to S and the other function keys are a number and ~
+fn sequence(parameters: &[u8], last: u8) -> Key {
+    let number: String = parameters.iter().take_while(|x| x.is_ascii_digit()).map(|&x| x as char).collect();
+    match (last, number.as_str()) {
+        (b'A', _) => Key::Up,
+        (b'B', _) => Key::Down,
+        (b'C', _) => Key::Right,
+        (b'D', _) => Key::Left,
+        (b'P', _) => Key::F1,
+        (b'Q', _) => Key::F2,
+        (b'R', _) => Key::F3,
+        (b'S', _) => Key::F4,
+        (b'~', "15") => Key::F5,
+        (b'~', "17") => Key::F6,
+        (b'~', "18") => Key::F7,
+        (b'~', "19") => Key::F8,
+        (b'~', "20") => Key::F9,
+        (b'~', "21") => Key::F10,
+        (b'~', "23") => Key::F11,
+        (b'~', "24") => Key::F12,
+        _ => Key::Unknown
+    }
+}
+
+// A line per two rows of pixels, ▀ in the color of the top pixel over the color of the bottom one
+fn blocks(frame: &Frame) -> Vec<String> {
+    (0..frame.height).step_by(2).map(|y| {
+        let mut line = String::new();
+        let mut last = None;
+        for x in 0..frame.width {
+            let top = frame.get(x, y);
+            let bottom = if y + 1 < frame.height { frame.get(x, y + 1) } else { top };
+            if last != Some((top, bottom)) {
+                line += &format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m", top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]);
+                last = Some((top, bottom));
+            }
+            line.push('▀');
+        }
+        line
+    }).collect()
+}
+
+// A line per four rows of pixels, a braille character per 2x4 pixels with a dot for every pixel
+// not in the background color
+fn dots(frame: &Frame, background: [f32; 4], foreground: [f32; 4]) -> Vec<String> {
+    // the bit of each dot, by column and row
+    const BITS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+    let [br, bg, bb, _] = video::bytes(background);
+    let [fr, fg, fb, _] = video::bytes(foreground);
+    let lit = |x: u32, y: u32| x < frame.width && y < frame.height && frame.get(x, y)[..3] != [br, bg, bb];
+
+    (0..frame.height).step_by(4).map(|y| {
+        let mut line = format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m", fr, fg, fb, br, bg, bb);
+        for x in (0..frame.width).step_by(2) {
+            let mut bits = 0;
+            for (column, rows) in BITS.iter().enumerate() {
+                for (row, bit) in rows.iter().enumerate() {
+                    if lit(x + column as u32, y + row as u32) {
+                        bits |= bit;
+                    }
+                }
+            }
+            line.push(::std::char::from_u32(0x2800 + bits).unwrap_or(' '));
+        }
+        line
+    }).collect()
+}
+
+// The registers, the next instruction, the stack, the timers and the keys held
+fn pane(stats: &Stats, keys: &[bool; 16], speed: Speed) -> Vec<String> {
+    let registers = &stats.registers;
+    let (byte1, byte2) = registers.opcode;
+    let mut lines = vec![
+        format!("PC {:03X}  I {:03X}", registers.pc, registers.i),
+        format!("{:02X}{:02X} {}", byte1, byte2, decode(byte1, byte2)),
+        String::new()
+    ];
+    for (row, values) in registers.v.chunks(4).enumerate() {
+        let cells: Vec<String> = values.iter().enumerate().map(|(i, value)| format!("V{:X} {:02X}", row * 4 + i, value)).collect();
+        lines.push(cells.join("  "));
+    }
+    lines.push(String::new());
+    lines.push(format!("DT {:02X}  ST {:02X}", registers.delay, registers.sound));
+    lines.push(match registers.stack {
+        (0, _) | (_, None) => "SP 0".to_string(),
+        (depth, Some(top)) => format!("SP {}  {:03X}", depth, top)
+    });
+    let held: Vec<String> = (0..16).filter(|&key| keys[key]).map(|key| format!("{:X}", key)).collect();
+    lines.push(format!("keys {}", held.join(" ")));
+    lines.push(String::new());
+    lines.push(match (stats.fault, speed.paused, registers.halted) {
+        _ if stats.crashed => "the emulator crashed".to_string(),
+        (Some(fault), _, _) => format!("stopped: {}, F8 resets", fault),
+        (None, true, _) => "paused, F5 goes on, F6 steps".to_string(),
+        (None, false, true) => "waiting for a key".to_string(),
+        (None, false, false) => String::new()
+    });
+    lines.push("Esc quits".to_string());
+    lines
+}
+
+#[cfg(unix)]
+mod raw {
+    use libc;
+    use std::io::{self, Write};
+    use std::mem;
+
+    // The terminal in raw mode on its other screen without a cursor, put back as it was when dropped
+    pub struct Raw {
+        original: libc::termios
+    }
+
+    impl Raw {
+        pub fn enter() -> Result<Raw, String> {
+            let original = unsafe {
+                let mut termios: libc::termios = mem::zeroed();
+                if libc::isatty(0) == 0 || libc::tcgetattr(0, &mut termios) != 0 {
+                    return Err("the terminal frontend needs a terminal to read keys from".to_string());
+                }
+                let original = termios;
+                libc::cfmakeraw(&mut termios);
+                libc::tcsetattr(0, libc::TCSANOW, &termios);
+                original
+            };
+            print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+            let _ = io::stdout().flush();
+            Ok(Raw { original })
+        }
+    }
+
+    impl Drop for Raw {
+        fn drop(&mut self) {
+            print!("\x1b[0m\x1b[?25h\x1b[?1049l");
+            let _ = io::stdout().flush();
+            unsafe {
+                libc::tcsetattr(0, libc::TCSANOW, &self.original);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod raw {
+    pub struct Raw;
+
+    impl Raw {
+        pub fn enter() -> Result<Raw, String> {
+            Err("the terminal frontend needs a Unix terminal".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use key::Key;
+
+    #[test]
+    fn arrows_come_as_csi_or_ss3() {
+        assert_eq!(parse(b"\x1b[A\x1b[B\x1bOC\x1bOD", true), (vec![Key::Up, Key::Down, Key::Right, Key::Left], 12));
+    }
+
+    #[test]
+    fn function_keys_come_as_letters_or_numbers() {
+        assert_eq!(parse(b"\x1bOP\x1b[15~\x1b[24~\x1b[1;2S", true), (vec![Key::F1, Key::F5, Key::F12, Key::F4], 19));
+    }
+
+    #[test]
+    fn keys_come_between_sequences() {
+        let (keys, _) = parse(b"w\x1b[Ds\r\x03", true);
+        assert_eq!(keys, vec![Key::W, Key::Left, Key::S, Key::Return, Key::Escape]);
+    }
+
+    #[test]
+    fn a_sequence_cut_by_a_read_waits_for_the_rest() {
+        assert_eq!(parse(b"w\x1b", false), (vec![Key::W], 1));
+        assert_eq!(parse(b"\x1b[2", false), (vec![], 0));
+        assert_eq!(parse(b"\x1b[24~", false), (vec![Key::F12], 5));
+    }
+
+    #[test]
+    fn a_lone_escape_is_escape_once_nothing_follows() {
+        assert_eq!(parse(b"\x1b", true), (vec![Key::Escape], 1));
+    }
+}
@@ -0,0 +1,192 @@
+use serde::Deserialize;
+use video::Frame;
+
+// Ways to blow the 64x32 screen up, all of them end at the exact size of the scale
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Upscaler {
+    Nearest,
+    // EPX, rounds the corners of diagonals
+    Scale2x,
+    Scale3x,
+    // Scale2x twice with the edges smoothed, close to what hqx does
+    Hqx,
+    // interpolated between pixels, soft like an LCD
+    Lcd
+}
+
+pub const NAMES: [&str; 5] = ["nearest", "scale2x", "scale3x", "hqx", "lcd"];
+
+impl Upscaler {
+    pub fn parse(name: &str) -> Option<Upscaler> {
+        match name {
+            "nearest" => Some(Upscaler::Nearest),
+            "scale2x" | "epx" => Some(Upscaler::Scale2x),
+            "scale3x" => Some(Upscaler::Scale3x),
+            "hqx" => Some(Upscaler::Hqx),
+            "lcd" => Some(Upscaler::Lcd),
+            _ => None
+        }
+    }
+
+    pub fn apply(self, frame: &Frame, scale: u32) -> Frame {
+        let (width, height) = (frame.width * scale, frame.height * scale);
+        // the pixel art filters need room for their steps, smaller scales stay sharp
+        let upscaled = match self {
+            Upscaler::Scale2x if scale >= 2 => scale2x(frame),
+            Upscaler::Scale3x if scale >= 3 => scale3x(frame),
+            Upscaler::Hqx if scale >= 4 => smooth(&scale2x(&scale2x(frame))),
+            Upscaler::Hqx if scale >= 2 => smooth(&scale2x(frame)),
+            Upscaler::Lcd => return lcd(frame, scale),
+            _ => frame.clone()
+        };
+        resize(&upscaled, width, height)
+    }
+}
+
+// Nearest neighbour to any size
+pub fn resize(frame: &Frame, width: u32, height: u32) -> Frame {
+    if frame.width == width && frame.height == height {
+        return frame.clone();
+    }
+    let mut resized = Frame::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            resized.set(x, y, frame.get(x * frame.width / width, y * frame.height / height));
+        }
+    }
+    resized
+}
+
+// The pixel at an offset, edges repeat
+fn near(frame: &Frame, x: u32, y: u32, dx: i32, dy: i32) -> [u8; 4] {
+    let x = (x as i32 + dx).max(0).min(frame.width as i32 - 1);
+    let y = (y as i32 + dy).max(0).min(frame.height as i32 - 1);
+    frame.get(x as u32, y as u32)
+}
+
+fn scale2x(frame: &Frame) -> Frame {
+    let mut scaled = Frame::new(frame.width * 2, frame.height * 2);
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let e = frame.get(x, y);
+            let (b, d, f, h) = (near(frame, x, y, 0, -1), near(frame, x, y, -1, 0), near(frame, x, y, 1, 0), near(frame, x, y, 0, 1));
+            let edge = b != h && d != f;
+            scaled.set(x * 2, y * 2, if edge && d == b { d } else { e });
+            scaled.set(x * 2 + 1, y * 2, if edge && b == f { f } else { e });
+            scaled.set(x * 2, y * 2 + 1, if edge && d == h { d } else { e });
+            scaled.set(x * 2 + 1, y * 2 + 1, if edge && h == f { f } else { e });
+        }
+    }
+    scaled
+}
+
+fn scale3x(frame: &Frame) -> Frame {
+    let mut scaled = Frame::new(frame.width * 3, frame.height * 3);
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let p = |dx, dy| near(frame, x, y, dx, dy);
+            let (a, b, c, d, e, f, g, h, i) = (p(-1, -1), p(0, -1), p(1, -1), p(-1, 0), p(0, 0), p(1, 0), p(-1, 1), p(0, 1), p(1, 1));
+            let mut block = [e; 9];
+            if b != h && d != f {
+                block[0] = if d == b { d } else { e };
+                block[1] = if (d == b && e != c) || (b == f && e != a) { b } else { e };
+                block[2] = if b == f { f } else { e };
+                block[3] = if (d == b && e != g) || (d == h && e != a) { d } else { e };
+                block[5] = if (b == f && e != i) || (h == f && e != c) { f } else { e };
+                block[6] = if d == h { d } else { e };
+                block[7] = if (d == h && e != i) || (h == f && e != g) { h } else { e };
+                block[8] = if h == f { f } else { e };
+            }
+            for (n, &color) in block.iter().enumerate() {
+                scaled.set(x * 3 + n as u32 % 3, y * 3 + n as u32 / 3, color);
+            }
+        }
+    }
+    scaled
+}
+
+// Pixels on an edge become the average of their neighbourhood, flat areas stay as they are
+fn smooth(frame: &Frame) -> Frame {
+    let mut smoothed = frame.clone();
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let e = frame.get(x, y);
+            let around = [near(frame, x, y, 0, -1), near(frame, x, y, -1, 0), near(frame, x, y, 1, 0), near(frame, x, y, 0, 1)];
+            if around.iter().all(|&color| color == e) {
+                continue;
+            }
+            let mut sum = [0u32; 4];
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    // the pixel itself counts as much as its 8 neighbours together
+                    let weight = if dx == 0 && dy == 0 { 8 } else { 1 };
+                    let color = near(frame, x, y, dx, dy);
+                    for channel in 0..4 {
+                        sum[channel] += color[channel] as u32 * weight;
+                    }
+                }
+            }
+            smoothed.set(x, y, [(sum[0] / 16) as u8, (sum[1] / 16) as u8, (sum[2] / 16) as u8, (sum[3] / 16) as u8]);
+        }
+    }
+    smoothed
+}
+
+// Bilinear between pixel centers, eased so pixels keep a flat middle and only their borders blur
+fn lcd(frame: &Frame, scale: u32) -> Frame {
+    let mut scaled = Frame::new(frame.width * scale, frame.height * scale);
+    let ease = |t: f32| t * t * (3.0 - 2.0 * t);
+    for y in 0..scaled.height {
+        for x in 0..scaled.width {
+            let sx = ((x as f32 + 0.5) / scale as f32 - 0.5).max(0.0);
+            let sy = ((y as f32 + 0.5) / scale as f32 - 0.5).max(0.0);
+            let (x0, y0) = (sx as u32, sy as u32);
+            let (x1, y1) = ((x0 + 1).min(frame.width - 1), (y0 + 1).min(frame.height - 1));
+            let (tx, ty) = (ease(sx - x0 as f32), ease(sy - y0 as f32));
+            let (a, b, c, d) = (frame.get(x0, y0), frame.get(x1, y0), frame.get(x0, y1), frame.get(x1, y1));
+            let mut color = [0; 4];
+            for channel in 0..4 {
+                let top = a[channel] as f32 + (b[channel] as f32 - a[channel] as f32) * tx;
+                let bottom = c[channel] as f32 + (d[channel] as f32 - c[channel] as f32) * tx;
+                color[channel] = (top + (bottom - top) * ty).round() as u8;
+            }
+            scaled.set(x, y, color);
+        }
+    }
+    scaled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Upscaler;
+    use video::Frame;
+
+    const W: [u8; 4] = [255, 255, 255, 255];
+    const B: [u8; 4] = [0, 0, 0, 255];
+
+    fn frame(width: u32, colors: &[[u8; 4]]) -> Frame {
+        let mut frame = Frame::new(width, colors.len() as u32 / width);
+        for (n, &color) in colors.iter().enumerate() {
+            frame.set(n as u32 % width, n as u32 / width, color);
+        }
+        frame
+    }
+
+    #[test]
+    fn scale2x_rounds_the_corners_of_a_diagonal() {
+        let scaled = Upscaler::Scale2x.apply(&frame(2, &[W, B, B, W]), 2);
+        assert_eq!(scaled.pixels, frame(4, &[
+            W, W, B, B,
+            W, B, W, B,
+            B, W, B, W,
+            B, B, W, W
+        ]).pixels);
+    }
+
+    #[test]
+    fn nearest_repeats_every_pixel() {
+        let scaled = Upscaler::Nearest.apply(&frame(2, &[W, B]), 2);
+        assert_eq!(scaled.pixels, frame(4, &[W, W, B, B, W, W, B, B]).pixels);
+    }
+}
@@ -0,0 +1,94 @@
+use data::Palette;
+use effects::{self, Effect};
+use flicker::Levels;
+use hardware::Gfx;
+use orientation::Orientation;
+use upscale::Upscaler;
+
+// The biggest scale a frame is made at, the window stretches it further
+pub const MAX_SCALE: u32 = 64;
+
+// An RGBA image, row by row, what the frontends show and screenshots save
+#[derive(Clone)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>
+}
+
+impl Frame {
+    pub fn new(width: u32, height: u32) -> Frame {
+        Frame { width, height, pixels: vec![0; (width * height * 4) as usize] }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: [u8; 4]) {
+        let i = ((y * self.width + x) * 4) as usize;
+        self.pixels[i..i + 4].copy_from_slice(&color);
+    }
+
+    #[cfg(feature = "piston")]
+    pub fn fill(&mut self, x: u32, y: u32, width: u32, height: u32, color: [u8; 4]) {
+        for y in y..(y + height).min(self.height) {
+            for x in x..(x + width).min(self.width) {
+                self.set(x, y, color);
+            }
+        }
+    }
+
+    // Copies another frame with its top left corner at x, y, cutting off what does not fit
+    #[cfg(feature = "piston")]
+    pub fn blit(&mut self, frame: &Frame, x: u32, y: u32) {
+        for row in 0..frame.height.min(self.height.saturating_sub(y)) {
+            for column in 0..frame.width.min(self.width.saturating_sub(x)) {
+                self.set(x + column, y + row, frame.get(column, row));
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Options {
+    pub scale: u32,
+    // lines of background between pixels, in pixels of the output
+    pub gap: u32,
+    // fills the gaps with a faint grid instead of the background
+    pub grid: bool,
+    pub upscaler: Upscaler,
+    pub effects: Vec<Effect>,
+    pub orientation: Orientation
+}
+
+pub fn render(colors: &Gfx, levels: &Levels, palette: Palette, options: &Options) -> Frame {
+    let scale = options.scale.max(1);
+    let turned = options.orientation.apply(&logical(colors, levels, palette));
+    let mut frame = options.upscaler.apply(&turned, scale);
+    effects::apply(&mut frame, palette, options);
+    frame
+}
+
+// One pixel per CHIP-8 pixel, each one mixed from the background to its color by its level
+pub fn logical(colors: &Gfx, levels: &Levels, palette: Palette) -> Frame {
+    let mut frame = Frame::new(colors[0].len() as u32, colors.len() as u32);
+    for y in 0..colors.len() {
+        for x in 0..colors[0].len() {
+            let color = mix(palette[0], palette[colors[y][x] as usize & 3], levels[y][x]);
+            frame.set(x as u32, y as u32, bytes(color));
+        }
+    }
+    frame
+}
+
+pub fn mix(a: [f32; 4], b: [f32; 4], amount: f32) -> [f32; 4] {
+    let channel = |i: usize| a[i] + (b[i] - a[i]) * amount;
+    [channel(0), channel(1), channel(2), 1.0]
+}
+
+pub fn bytes(color: [f32; 4]) -> [u8; 4] {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [channel(color[0]), channel(color[1]), channel(color[2]), channel(color[3])]
+}
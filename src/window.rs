@@ -0,0 +1,150 @@
+use config::Geometry;
+use frontend;
+#[cfg(feature = "gamepad")]
+use gamepad::Gamepads;
+use hardware::Control;
+use key;
+use piston_window::texture::{CreateTexture, Filter as TextureFilter, Format, UpdateTexture};
+use piston_window::*;
+// the trait, for the size of the glutin window
+use piston_window::Window as _;
+use serde_json;
+use std::mem;
+use video::Frame;
+
+// The piston window, with F11 for fullscreen and its place and size kept between runs
+pub struct Window {
+    pub window: PistonWindow,
+    pub fullscreen: bool,
+    // what gets saved, the size before going fullscreen
+    windowed: Geometry,
+    // opened with the window, so every game it plays shares them
+    #[cfg(feature = "gamepad")]
+    pub gamepads: Option<Gamepads>
+}
+
+impl Window {
+    // Opens where it was left, or centered on the monitor the first time.
+    // The remembered size wins over this one when restoring
+    pub fn open(size: [u32; 2], restore_size: bool) -> Result<Window, String> {
+        let geometry = Geometry::load();
+        let size = match geometry {
+            Some(geometry) if restore_size => geometry.size,
+            _ => size
+        };
+        let fullscreen = geometry.is_some_and(|geometry| geometry.fullscreen);
+
+        let mut window: PistonWindow = WindowSettings::new("chip-8", size)
+            .resizable(true)
+            .fullscreen(fullscreen)
+            .build()
+            .map_err(|e| format!("could not open a window: {}", e))?;
+
+        match geometry {
+            Some(geometry) => window.window.set_position(geometry.position),
+            None => {
+                let glutin = window.window.ctx.window();
+                let monitor = glutin.get_current_monitor();
+                let (origin, area) = (monitor.get_position(), monitor.get_dimensions().to_logical(monitor.get_hidpi_factor()));
+                let x = origin.x as i32 + (area.width as i32 - size[0] as i32) / 2;
+                let y = origin.y as i32 + (area.height as i32 - size[1] as i32) / 2;
+                window.window.set_position([x, y]);
+            }
+        }
+        let position = window.window.get_position().map_or([0, 0], |p| [p.x, p.y]);
+
+        Ok(Window {
+            window,
+            fullscreen,
+            windowed: Geometry { position, size, fullscreen },
+            #[cfg(feature = "gamepad")]
+            gamepads: Gamepads::open()
+        })
+    }
+
+    // F11, and the moves and resizes to remember
+    pub fn handle(&mut self, event: &Event) {
+        match *event {
+            Event::Input(Input::Button(ButtonArgs { button: Button::Keyboard(Key::F11), state: ButtonState::Press, .. }), _) => {
+                self.fullscreen = !self.fullscreen;
+                let glutin = self.window.window.ctx.window();
+                glutin.set_fullscreen(if self.fullscreen { Some(glutin.get_current_monitor()) } else { None });
+            },
+            Event::Input(Input::Resize(_), _) if !self.fullscreen => {
+                let size = self.window.window.size();
+                self.windowed.size = [size.width as u32, size.height as u32];
+            },
+            _ => ()
+        }
+        if event.render_args().is_some() && !self.fullscreen {
+            if let Some(position) = self.window.window.get_position() {
+                self.windowed.position = [position.x, position.y];
+            }
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.window.window.size()
+    }
+
+    // Only a convenience for the next time, a window that can't be remembered is not a failure
+    pub fn save(&self) {
+        if let Err(error) = (Geometry { fullscreen: self.fullscreen, ..self.windowed }.save()) {
+            eprintln!("the window size and position were not saved: {}", error);
+        }
+    }
+}
+
+// The piston input, the key events of the window as the emulator's, kept until polled.
+// Gamepads come from the gamepad feature, the glutin window does not send their events
+#[derive(Default)]
+pub struct Controls {
+    events: Vec<frontend::Event>
+}
+
+impl Controls {
+    // Everything else is ignored
+    pub fn feed(&mut self, input: &Input) {
+        if let Input::Button(ButtonArgs { button: Button::Keyboard(key), state, .. }) = *input {
+            let held = state == ButtonState::Press;
+            self.events.push(frontend::Event::Control(Control::Key(keyboard(key)), held));
+        }
+    }
+}
+
+impl frontend::Input for Controls {
+    fn poll(&mut self) -> Vec<frontend::Event> {
+        mem::take(&mut self.events)
+    }
+}
+
+// Piston's keys go by the same names as the emulator's
+fn keyboard(key: Key) -> key::Key {
+    serde_json::to_value(key).ok().and_then(|name| serde_json::from_value(name).ok()).unwrap_or(key::Key::Unknown)
+}
+
+// The biggest whole scale a width x height picture fits the window at, or any scale when fractional,
+// and the top left corner that centers it with bars around
+pub fn fit(window: Size, [width, height]: [f64; 2], fractional: bool) -> (f64, [f64; 2]) {
+    let fits = (window.width / width).min(window.height / height);
+    let scale = if fractional { fits } else { fits.floor() }.max(1.0);
+    let origin = [((window.width - width * scale) / 2.0).max(0.0).floor(), ((window.height - height * scale) / 2.0).max(0.0).floor()];
+    (scale, origin)
+}
+
+// The frame becomes a texture scaled without filtering, reused while its size stays the same
+pub fn upload(context: &mut G2dTextureContext, texture: Option<G2dTexture>, frame: &Frame) -> Result<G2dTexture, String> {
+    let size = [frame.width, frame.height];
+    match texture {
+        Some(mut texture) if texture.get_size() == (frame.width, frame.height) => {
+            UpdateTexture::update(&mut texture, context, Format::Rgba8, &frame.pixels, [0, 0], size)
+                .map_err(|e| format!("could not update the screen: {:?}", e))?;
+            Ok(texture)
+        },
+        _ => {
+            let settings = TextureSettings::new().filter(TextureFilter::Nearest);
+            CreateTexture::create(context, Format::Rgba8, &frame.pixels, size, &settings)
+                .map_err(|e| format!("could not create the screen: {:?}", e))
+        }
+    }
+}